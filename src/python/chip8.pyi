@@ -0,0 +1,38 @@
+# Type hints for the `chip8` extension module, picked up by maturin.
+
+WIDTH: int
+HEIGHT: int
+
+class Screen:
+    """Read-only HEIGHT x WIDTH buffer of 0/1 bytes; view it with numpy.asarray."""
+
+    def __len__(self) -> int: ...
+    def __buffer__(self, flags: int) -> memoryview: ...
+
+class Chip8:
+    def __init__(self, seed: int | None = None) -> None: ...
+    def load_rom(self, rom: bytes) -> None: ...
+    def reset(self) -> None: ...
+    def run_frame(self) -> None: ...
+    def tick(self) -> None: ...
+    def set_key(self, key: int) -> None: ...
+    def unset_key(self, key: int) -> None: ...
+    @property
+    def keys(self) -> int: ...
+    @property
+    def memory(self) -> bytes: ...
+    def write_memory(self, address: int, data: bytes) -> None: ...
+    @property
+    def registers(self) -> list[int]: ...
+    def set_register(self, index: int, value: int) -> None: ...
+    @property
+    def index(self) -> int: ...
+    @property
+    def pc(self) -> int: ...
+    @property
+    def beeping(self) -> bool: ...
+    @property
+    def error(self) -> str | None: ...
+    def save_state(self) -> bytes: ...
+    def load_state(self, state: bytes) -> None: ...
+    def screen(self) -> Screen: ...
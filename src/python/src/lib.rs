@@ -0,0 +1,241 @@
+// Python bindings for the emulator core, built into the `chip8` extension
+// module with maturin. `Chip8` wraps the same machine the web build runs;
+// invalid arguments raise `ValueError` instead of being ignored, and the
+// screen comes back as a read-only 32x64 buffer of 0/1 bytes that
+// `numpy.asarray` can view without copying.
+use std::{
+    ffi::{c_char, c_int, c_void},
+    ptr,
+};
+
+use chip8_emulator::Chip8 as Core;
+use pyo3::{
+    exceptions::{PyBufferError, PyValueError},
+    ffi,
+    prelude::*,
+    types::PyBytes,
+};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+const KEY_COUNT: u8 = 16;
+const REG_COUNT: usize = 16;
+
+#[pyclass(name = "Chip8", module = "chip8")]
+pub struct Chip8 {
+    core: Core,
+}
+
+#[pymethods]
+impl Chip8 {
+    // Seeded machines give the same random numbers on every run
+    #[new]
+    #[pyo3(signature = (seed=None))]
+    pub fn new(seed: Option<u64>) -> Self {
+        let core = match seed {
+            Some(seed) => Core::with_seed(seed),
+            None => Core::new(),
+        };
+        Chip8 { core }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        if rom.len() > self.core.max_rom_size() {
+            return Err(PyValueError::new_err(format!(
+                "a {} byte ROM does not fit in {} bytes",
+                rom.len(),
+                self.core.max_rom_size()
+            )));
+        }
+        self.core.load_rom(rom);
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    pub fn run_frame(&mut self) {
+        self.core.run_frame();
+    }
+
+    // One instruction and a timer decrement
+    pub fn tick(&mut self) {
+        self.core.tick();
+    }
+
+    pub fn set_key(&mut self, key: u8) -> PyResult<()> {
+        self.core.set_key(check_key(key)?);
+        Ok(())
+    }
+
+    pub fn unset_key(&mut self, key: u8) -> PyResult<()> {
+        self.core.unset_key(check_key(key)?);
+        Ok(())
+    }
+
+    // Keys held, one bit per key
+    #[getter]
+    pub fn keys(&self) -> u16 {
+        self.core.get_keys()
+    }
+
+    // All 4096 bytes
+    #[getter]
+    pub fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.core.memory())
+    }
+
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        if self.core.write_memory(address, data) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!(
+                "{} bytes at {:#X} do not fit in memory",
+                data.len(),
+                address
+            )))
+        }
+    }
+
+    // V0-VF
+    #[getter]
+    pub fn registers(&self) -> Vec<u8> {
+        self.core.registers().to_vec()
+    }
+
+    pub fn set_register(&mut self, index: usize, value: u8) -> PyResult<()> {
+        if index >= REG_COUNT {
+            return Err(PyValueError::new_err(format!(
+                "register index {} is out of range",
+                index
+            )));
+        }
+        self.core.set_register(index, value);
+        Ok(())
+    }
+
+    #[getter]
+    pub fn index(&self) -> u16 {
+        self.core.index_register()
+    }
+
+    #[getter]
+    pub fn pc(&self) -> usize {
+        self.core.program_counter()
+    }
+
+    #[getter]
+    pub fn beeping(&self) -> bool {
+        self.core.is_beeping()
+    }
+
+    // Description of the fault that stopped the machine, if any
+    #[getter]
+    pub fn error(&self) -> Option<String> {
+        self.core.get_error()
+    }
+
+    pub fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.core.save_state())
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        if self.core.load_state(state) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(
+                "not a save state this version can read",
+            ))
+        }
+    }
+
+    // A copy of the screen as it is now
+    pub fn screen(&self) -> Screen {
+        Screen {
+            pixels: self.core.frame_buffer().to_vec(),
+            shape: [HEIGHT as ffi::Py_ssize_t, WIDTH as ffi::Py_ssize_t],
+            strides: [WIDTH as ffi::Py_ssize_t, 1],
+        }
+    }
+}
+
+fn check_key(key: u8) -> PyResult<u8> {
+    if key < KEY_COUNT {
+        Ok(key)
+    } else {
+        Err(PyValueError::new_err(format!(
+            "key {} is out of range",
+            key
+        )))
+    }
+}
+
+// Read-only buffer of one byte per pixel, row by row. The shape and strides
+// live here because the buffer protocol points into them for as long as a
+// view is open, and a view keeps its screen alive.
+#[pyclass(module = "chip8", frozen)]
+pub struct Screen {
+    pixels: Vec<u8>,
+    shape: [ffi::Py_ssize_t; 2],
+    strides: [ffi::Py_ssize_t; 2],
+}
+
+#[pymethods]
+impl Screen {
+    pub fn __len__(&self) -> usize {
+        self.pixels.len()
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("the screen is read-only"));
+        }
+        let screen = slf.get();
+        let wants = |flag| flags & flag == flag;
+        let (ndim, shape) = if wants(ffi::PyBUF_ND) {
+            (2, screen.shape.as_ptr() as *mut _)
+        } else {
+            (1, ptr::null_mut())
+        };
+        let view = &mut *view;
+        view.buf = screen.pixels.as_ptr() as *mut c_void;
+        view.len = screen.pixels.len() as ffi::Py_ssize_t;
+        view.readonly = 1;
+        view.itemsize = 1;
+        view.format = if wants(ffi::PyBUF_FORMAT) {
+            b"B\0".as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        view.ndim = ndim;
+        view.shape = shape;
+        view.strides = if wants(ffi::PyBUF_STRIDES) {
+            screen.strides.as_ptr() as *mut _
+        } else {
+            ptr::null_mut()
+        };
+        view.suboffsets = ptr::null_mut();
+        view.internal = ptr::null_mut();
+        view.obj = slf.into_any().into_ptr();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+#[pymodule]
+pub fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Chip8>()?;
+    module.add_class::<Screen>()?;
+    module.add("WIDTH", WIDTH)?;
+    module.add("HEIGHT", HEIGHT)?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! The `chip8` module as Python sees it: the machine runs ROMs, bad
+//! arguments raise `ValueError`, and the screen is a 2-D byte buffer.
+
+use std::ffi::CString;
+
+use pyo3::{prelude::*, types::PyDict};
+
+// Runs `code` with the module imported as `chip8` and `rom` set to BRIX
+fn run_python(code: &str) {
+    let rom = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../test_roms/BRIX.ch8"
+    ))
+    .expect("failed to read BRIX.ch8");
+    Python::attach(|py| {
+        let module = pyo3::wrap_pymodule!(chip8::chip8)(py);
+        let globals = PyDict::new(py);
+        globals.set_item("chip8", module).unwrap();
+        globals
+            .set_item("rom", pyo3::types::PyBytes::new(py, &rom))
+            .unwrap();
+        let code = CString::new(code).unwrap();
+        if let Err(err) = py.run(&code, Some(&globals), None) {
+            err.print(py);
+            panic!("Python code failed");
+        }
+    });
+}
+
+#[test]
+fn runs_a_rom_and_shows_the_screen() {
+    run_python(
+        r#"
+machine = chip8.Chip8(seed=1)
+machine.load_rom(rom)
+assert machine.pc == 0x200
+for _ in range(120):
+    machine.run_frame()
+assert machine.error is None
+assert machine.registers[0xE] == 5
+
+screen = memoryview(machine.screen())
+assert screen.shape == (chip8.HEIGHT, chip8.WIDTH)
+assert screen.format == "B" and screen.readonly
+assert set(screen.tobytes()) == {0, 1}
+# The paddle sits on the bottom row
+assert 1 in screen.tolist()[31]
+"#,
+    );
+}
+
+#[test]
+fn keys_memory_and_registers() {
+    run_python(
+        r#"
+machine = chip8.Chip8()
+machine.set_key(4)
+machine.set_key(0xF)
+assert machine.keys == (1 << 4) | (1 << 0xF)
+machine.unset_key(4)
+assert machine.keys == 1 << 0xF
+
+# Patch over a program that only jumps to itself
+machine.load_rom(bytes([0x12, 0x00]))
+machine.write_memory(0x200, bytes([0x6A, 0x2C, 0xA3, 0x00]))
+machine.tick()
+machine.tick()
+assert machine.registers[0xA] == 0x2C
+assert machine.index == 0x300
+assert machine.pc == 0x204
+assert machine.memory[0x200:0x204] == bytes([0x6A, 0x2C, 0xA3, 0x00])
+machine.set_register(3, 7)
+assert machine.registers[3] == 7
+
+for call in (
+    lambda: machine.set_key(16),
+    lambda: machine.set_register(16, 0),
+    lambda: machine.write_memory(0xFFF, b"\0\0"),
+    lambda: machine.load_state(b"not a state"),
+    lambda: machine.load_rom(bytes(0x1000 - 0x200 + 1)),
+):
+    try:
+        call()
+    except ValueError:
+        pass
+    else:
+        raise AssertionError("expected ValueError")
+"#,
+    );
+}
+
+#[test]
+fn save_state_round_trips() {
+    run_python(
+        r#"
+machine = chip8.Chip8(seed=2)
+machine.load_rom(rom)
+for _ in range(100):
+    machine.run_frame()
+state = machine.save_state()
+expected = []
+for _ in range(60):
+    machine.run_frame()
+    expected.append(bytes(machine.screen()))
+
+machine.load_state(state)
+for frame in expected:
+    machine.run_frame()
+    assert bytes(machine.screen()) == frame
+"#,
+    );
+}
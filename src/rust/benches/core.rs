@@ -0,0 +1,186 @@
+//! Benchmarks for the core, so interpreter work can be measured:
+//!
+//! - `ips`: every ROM in `test_roms/` for a second of emulated time, reported
+//!   as instructions per second. The display wait quirk is turned off so a
+//!   frame never idles after a draw; ROMs that wait for a key still idle.
+//! - `display_sprite`: 15-row sprites drawn at 256 positions, with wrapping
+//!   and with clipping.
+//! - `snapshot`: saving and restoring a game in progress.
+//! - `render`: the RGBA screen, the phosphor filter and each upscaler.
+//! - `batch`: a frame on every machine of a `Chip8Batch`, on one thread and
+//!   on several.
+//!
+//! Run with `cargo bench --bench core`, or pick a group with e.g.
+//! `cargo bench --bench core -- ips`.
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use chip8_emulator::{BuiltinPalette, Chip8, Chip8Batch, FlickerFilter, Upscaler};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+
+const INSTRUCTIONS_PER_SECOND: u32 = 60_000;
+const FRAMES_PER_SECOND: u32 = 60;
+const UPSCALE_FACTOR: usize = 4;
+const BATCH_SIZE: u64 = 64;
+const BATCH_THREADS: usize = 4;
+
+fn rom_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_roms")
+}
+
+fn load(name: &str) -> Vec<u8> {
+    let path = rom_dir().join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err))
+}
+
+// Loaded and set up after `load_rom`, which applies the ROM's own settings
+fn machine(rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::with_seed(1);
+    chip8.load_rom(rom);
+    let mut quirks = chip8.get_quirks();
+    quirks.display_wait = false;
+    chip8.set_quirks(quirks);
+    chip8.set_instructions_per_second(INSTRUCTIONS_PER_SECOND);
+    chip8
+}
+
+// A game some way in, with a busy screen
+fn game_in_progress() -> Chip8 {
+    let mut chip8 = machine(&load("BRIX.ch8"));
+    for _ in 0..120 {
+        chip8.run_frame();
+    }
+    chip8
+}
+
+fn ips(c: &mut Criterion) {
+    let mut names: Vec<String> = fs::read_dir(rom_dir())
+        .expect("test_roms directory is missing")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".ch8"))
+        .collect();
+    names.sort();
+
+    let mut group = c.benchmark_group("ips");
+    group
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(2))
+        .throughput(Throughput::Elements(INSTRUCTIONS_PER_SECOND as u64));
+    for name in names {
+        let rom = load(&name);
+        group.bench_with_input(BenchmarkId::from_parameter(&name), &rom, |b, rom| {
+            b.iter_batched_ref(
+                || machine(rom),
+                |chip8| {
+                    for _ in 0..FRAMES_PER_SECOND {
+                        chip8.run_frame();
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn display_sprite(c: &mut Criterion) {
+    let mut group = c.benchmark_group("display_sprite");
+    group.throughput(Throughput::Elements(256));
+    for clipping in [false, true] {
+        let mut chip8 = Chip8::new();
+        let mut quirks = chip8.get_quirks();
+        quirks.clipping = clipping;
+        quirks.display_wait = false;
+        chip8.set_quirks(quirks);
+        // Spread V0-VF over the screen, past the edges too, and point I at
+        // the font so the sprite has lit pixels
+        for x in 0..16u16 {
+            chip8.handle_opcode(0x6000 | x << 8 | (x * 5));
+        }
+        chip8.handle_opcode(0xA050);
+
+        let label = if clipping { "clip" } else { "wrap" };
+        group.bench_function(label, |b| {
+            b.iter(|| {
+                for x in 0..16u16 {
+                    for y in 0..16u16 {
+                        chip8.handle_opcode(0xD00F | x << 8 | y << 4);
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn snapshot(c: &mut Criterion) {
+    let mut chip8 = game_in_progress();
+    let state = chip8.save_state();
+
+    let mut group = c.benchmark_group("snapshot");
+    group.bench_function("save", |b| b.iter(|| chip8.save_state()));
+    group.bench_function("load", |b| b.iter(|| chip8.load_state(black_box(&state))));
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut chip8 = game_in_progress();
+
+    let mut group = c.benchmark_group("render");
+    group.bench_function("rgba", |b| {
+        b.iter(|| {
+            // Changing the palette forces a redraw
+            chip8.set_palette(BuiltinPalette::Monochrome);
+            chip8.rgba_screen().len()
+        })
+    });
+
+    // One filtered frame each time: a sprite toggled so the screen keeps
+    // changing, the frame ended and the result drawn
+    let mut filtered = game_in_progress();
+    filtered.set_flicker_filter(FlickerFilter::Phosphor);
+    filtered.set_instructions_per_second(0);
+    filtered.handle_opcode(0xA050);
+    group.bench_function("phosphor", |b| {
+        b.iter(|| {
+            filtered.handle_opcode(0xD00F);
+            filtered.run_frame();
+            filtered.rgba_screen().len()
+        })
+    });
+
+    for (upscaler, label) in [
+        (Upscaler::Nearest, "nearest"),
+        (Upscaler::Epx, "epx"),
+        (Upscaler::Hqx, "hqx"),
+        (Upscaler::Crt, "crt"),
+    ] {
+        group.bench_function(BenchmarkId::new(label, UPSCALE_FACTOR), |b| {
+            b.iter(|| {
+                chip8.set_upscaler(upscaler, UPSCALE_FACTOR);
+                chip8.upscaled_screen().len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let seeds: Vec<u64> = (0..BATCH_SIZE).collect();
+    let mut batch = Chip8Batch::new(&seeds);
+    batch.load_rom(&load("BRIX.ch8"));
+
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(BATCH_SIZE));
+    group.bench_function("step", |b| b.iter(|| batch.step()));
+    group.bench_function(BenchmarkId::new("step_threaded", BATCH_THREADS), |b| {
+        b.iter(|| batch.step_threaded(BATCH_THREADS))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, ips, display_sprite, snapshot, render, batch);
+criterion_main!(benches);
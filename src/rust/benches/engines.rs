@@ -0,0 +1,54 @@
+//! Compares the plain interpreter with the cached-decode and block JIT engines
+//! on a few ROMs run far faster than real time, as turbo mode and batch
+//! analysis do.
+//!
+//! Run with `cargo bench --bench engines`.
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{Chip8, ExecutionEngine};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const ROMS: &[&str] = &["BRIX.ch8", "INVADERS.ch8", "PONG.ch8"];
+const FRAMES: u64 = 60;
+// 1000 instructions per frame
+const INSTRUCTIONS_PER_SECOND: u32 = 60_000;
+
+fn load(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test_roms")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err))
+}
+
+fn engines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engines");
+    group.throughput(Throughput::Elements(
+        FRAMES * INSTRUCTIONS_PER_SECOND as u64 / 60,
+    ));
+    for &name in ROMS {
+        let rom = load(name);
+        for (engine, label) in [
+            (ExecutionEngine::Interpreter, "interpreter"),
+            (ExecutionEngine::CachedDecode, "cached_decode"),
+            (ExecutionEngine::BlockJit, "block_jit"),
+        ] {
+            group.bench_with_input(BenchmarkId::new(label, name), &rom, |b, rom| {
+                b.iter(|| {
+                    let mut chip8 = Chip8::with_seed(1);
+                    chip8.set_execution_engine(engine);
+                    chip8.set_instructions_per_second(INSTRUCTIONS_PER_SECOND);
+                    chip8.load_rom(rom);
+                    for _ in 0..FRAMES {
+                        chip8.run_frame();
+                    }
+                    chip8
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
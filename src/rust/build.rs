@@ -0,0 +1,123 @@
+// Compiles `roms.json` into `$OUT_DIR/rom_database.rs`, a table of known ROMs
+// sorted by SHA-1 so the crate can binary search it without parsing JSON at
+// run time.
+use std::{env, fs, path::Path};
+
+use serde_json::Value;
+
+const DATABASE: &str = "roms.json";
+const PLATFORMS: &[(&str, &str)] = &[
+    ("chip8", "Platform::Chip8"),
+    ("schip", "Platform::SuperChip"),
+    ("xochip", "Platform::XoChip"),
+];
+const QUIRK_PROFILES: &[(&str, &str)] = &[
+    ("default", "Quirks::DEFAULT"),
+    ("chip8", "Quirks::COSMAC_VIP"),
+    ("schip", "Quirks::SCHIP"),
+    ("xochip", "Quirks::XO_CHIP"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", DATABASE);
+
+    let json = fs::read_to_string(DATABASE).expect("failed to read roms.json");
+    let entries: Vec<Value> = serde_json::from_str(&json).expect("roms.json is not valid JSON");
+
+    let mut rows: Vec<(String, String)> = entries.iter().map(entry_to_rust).collect();
+    rows.sort();
+    for pair in rows.windows(2) {
+        assert!(pair[0].0 != pair[1].0, "duplicate sha1 {}", pair[0].0);
+    }
+
+    let mut out = String::from("pub(crate) const ROM_DATABASE: &[RomEntry] = &[\n");
+    for (_, row) in rows {
+        out.push_str(&row);
+    }
+    out.push_str("];\n");
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("rom_database.rs");
+    fs::write(path, out).expect("failed to write rom_database.rs");
+}
+
+fn field<'a>(entry: &'a Value, name: &str) -> &'a Value {
+    entry
+        .get(name)
+        .unwrap_or_else(|| panic!("roms.json entry is missing \"{}\": {}", name, entry))
+}
+
+fn string_field<'a>(entry: &'a Value, name: &str) -> &'a str {
+    field(entry, name)
+        .as_str()
+        .unwrap_or_else(|| panic!("\"{}\" must be a string: {}", name, entry))
+}
+
+fn lookup(table: &[(&str, &'static str)], name: &str) -> &'static str {
+    table
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| *value)
+        .unwrap_or_else(|| panic!("unknown name \"{}\" in roms.json", name))
+}
+
+// "#RRGGBB" to 0xRRGGBB
+fn parse_color(color: &Value) -> u32 {
+    color
+        .as_str()
+        .and_then(|color| color.strip_prefix('#'))
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .unwrap_or_else(|| panic!("colors must look like \"#RRGGBB\", got {}", color))
+}
+
+// Returns the hash, for sorting, and the `RomEntry` literal
+fn entry_to_rust(entry: &Value) -> (String, String) {
+    let sha1 = string_field(entry, "sha1").to_ascii_lowercase();
+    assert!(
+        sha1.len() == 40 && sha1.bytes().all(|b| b.is_ascii_hexdigit()),
+        "bad sha1 \"{}\"",
+        sha1
+    );
+    let hash_bytes: Vec<String> = (0..20)
+        .map(|i| format!("0x{}", &sha1[i * 2..i * 2 + 2]))
+        .collect();
+
+    let ticks_per_frame = field(entry, "ticks_per_frame")
+        .as_u64()
+        .filter(|&ticks| ticks > 0)
+        .unwrap_or_else(|| panic!("\"ticks_per_frame\" must be a positive integer: {}", entry));
+
+    let keys: Vec<String> = field(entry, "keys")
+        .as_object()
+        .unwrap_or_else(|| panic!("\"keys\" must be an object: {}", entry))
+        .iter()
+        .map(|(key, value)| {
+            let chip8_key = value
+                .as_u64()
+                .filter(|&k| k < 16)
+                .unwrap_or_else(|| panic!("key \"{}\" must map to 0-15: {}", key, entry));
+            format!("({:?}, {})", key, chip8_key)
+        })
+        .collect();
+
+    let palette = field(entry, "palette")
+        .as_array()
+        .filter(|colors| colors.len() == 2)
+        .unwrap_or_else(|| panic!("\"palette\" must be [background, foreground]: {}", entry));
+
+    let row = format!(
+        "    RomEntry {{\n        sha1: [{}],\n        title: {:?},\n        author: {:?},\n        \
+         platform: {},\n        quirks: {},\n        ticks_per_frame: {},\n        \
+         keys: &[{}],\n        palette: [0x{:06X}, 0x{:06X}],\n    }},\n",
+        hash_bytes.join(", "),
+        string_field(entry, "title"),
+        string_field(entry, "author"),
+        lookup(PLATFORMS, string_field(entry, "platform")),
+        lookup(QUIRK_PROFILES, string_field(entry, "quirks")),
+        ticks_per_frame,
+        keys.join(", "),
+        parse_color(&palette[0]),
+        parse_color(&palette[1]),
+    );
+    (sha1, row)
+}
@@ -0,0 +1,19 @@
+// Feeds arbitrary opcodes straight to the dispatcher, bypassing the program
+// counter, so every instruction is reached with arbitrary machine state.
+#![no_main]
+
+use chip8_emulator::{Chip8, QUIRK_PROFILES};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (profile, opcodes) = match data.split_first() {
+        Some((&profile, opcodes)) => (profile, opcodes),
+        None => return,
+    };
+
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.set_quirks(QUIRK_PROFILES[profile as usize % QUIRK_PROFILES.len()].1);
+    for opcode in opcodes.chunks_exact(2) {
+        chip8.handle_opcode(u16::from_be_bytes([opcode[0], opcode[1]]));
+    }
+});
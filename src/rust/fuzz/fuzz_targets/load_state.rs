@@ -0,0 +1,41 @@
+// Loads arbitrary bytes as a save state, then runs the machine for a bounded
+// number of frames. The first byte picks the execution engine and the memory
+// layout flags. A state that loads must run without panicking and save again
+// into one that loads.
+#![no_main]
+
+use chip8_emulator::{Chip8, ExecutionEngine};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 100;
+const ENGINES: [ExecutionEngine; 3] = [
+    ExecutionEngine::Interpreter,
+    ExecutionEngine::CachedDecode,
+    ExecutionEngine::BlockJit,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let (flags, state) = match data.split_first() {
+        Some((&flags, state)) => (flags, state),
+        None => return,
+    };
+
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.set_execution_engine(ENGINES[(flags >> 2) as usize % ENGINES.len()]);
+    chip8.set_vip_layout(flags & 1 != 0);
+    chip8.set_stack_in_memory(flags & 2 != 0);
+    if !chip8.load_state(state) {
+        return;
+    }
+
+    for frame in 0..FRAMES {
+        let key = (frame % 16) as u8;
+        chip8.set_key(key);
+        chip8.run_frame();
+        chip8.unset_key(key);
+        if chip8.error().is_some() {
+            break;
+        }
+    }
+    assert!(Chip8::with_seed(0).load_state(&chip8.save_state()));
+});
@@ -0,0 +1,56 @@
+// Loads arbitrary bytes as a ROM on a machine configured from the first two
+// bytes: the execution engine, then the quirks profile and the memory layout
+// flags. After every frame the machine must be in the same state as an
+// interpreter configured the same way.
+#![no_main]
+
+use chip8_emulator::{Chip8, ExecutionEngine, QUIRK_PROFILES};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 100;
+const ENGINES: [ExecutionEngine; 3] = [
+    ExecutionEngine::Interpreter,
+    ExecutionEngine::CachedDecode,
+    ExecutionEngine::BlockJit,
+];
+
+// Bits 0-2 of `flags` are the VIP layout, the in-memory stack and write
+// protection; the rest pick the quirks profile
+fn machine(engine: ExecutionEngine, flags: u8, rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.set_quirks(QUIRK_PROFILES[(flags >> 3) as usize % QUIRK_PROFILES.len()].1);
+    chip8.set_execution_engine(engine);
+    chip8.set_vip_layout(flags & 1 != 0);
+    chip8.set_stack_in_memory(flags & 2 != 0);
+    chip8.set_write_protection(flags & 4 != 0);
+    chip8.load_rom(rom);
+    chip8
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (engine, flags, rom) = match data {
+        [engine, flags, rom @ ..] => (ENGINES[*engine as usize % ENGINES.len()], *flags, rom),
+        _ => return,
+    };
+
+    let mut chip8 = machine(engine, flags, rom);
+    let mut expected = machine(ExecutionEngine::Interpreter, flags, rom);
+    for frame in 0..FRAMES {
+        let key = (frame % 16) as u8;
+        for machine in [&mut chip8, &mut expected] {
+            machine.set_key(key);
+            machine.run_frame();
+            machine.unset_key(key);
+        }
+        // Save states hold everything a program can change
+        assert!(
+            chip8.save_state() == expected.save_state(),
+            "frame {}",
+            frame
+        );
+        assert_eq!(chip8.error(), expected.error(), "frame {}", frame);
+        if chip8.error().is_some() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,27 @@
+// Loads arbitrary bytes as a ROM and runs it for a bounded number of frames
+// under every quirks profile. The core must report faults, never panic.
+#![no_main]
+
+use chip8_emulator::{Chip8, QUIRK_PROFILES};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 200;
+
+fuzz_target!(|rom: &[u8]| {
+    for &(_, quirks) in QUIRK_PROFILES {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(quirks);
+        chip8.load_rom(rom);
+
+        for frame in 0..FRAMES {
+            // Cycle through the keypad so key-dependent paths are reached
+            let key = (frame % 16) as u8;
+            chip8.set_key(key);
+            chip8.run_frame();
+            chip8.unset_key(key);
+            if chip8.error().is_some() {
+                break;
+            }
+        }
+    }
+});
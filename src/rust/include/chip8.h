@@ -0,0 +1,132 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated from src/capi.rs by cbindgen. Do not edit; run `CHIP8_UPDATE_HEADER=1 cargo test --test capi` instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct Chip8 Chip8;
+
+// Pixels per row of the screen.
+#define CHIP8_SCREEN_WIDTH 64
+
+// Rows of the screen.
+#define CHIP8_SCREEN_HEIGHT 32
+
+// Bytes `chip8_screen` writes: one per pixel, row by row.
+#define CHIP8_SCREEN_SIZE 2048
+
+// Keys 0x0-0xF.
+#define CHIP8_KEY_COUNT 16
+
+// Result of every call that can fail.
+typedef enum Chip8Status {
+  CHIP8_STATUS_OK = 0,
+  // A required pointer was null.
+  CHIP8_STATUS_NULL_POINTER = 1,
+  // A key or ROM size was out of range.
+  CHIP8_STATUS_INVALID_ARGUMENT = 2,
+  // The output buffer is too small. Calls that say so report the size
+  // needed.
+  CHIP8_STATUS_BUFFER_TOO_SMALL = 3,
+  // The bytes are not a save state this version can read.
+  CHIP8_STATUS_INVALID_STATE = 4,
+  // The program hit an error, such as an unknown opcode, and the machine
+  // stopped. `chip8_load_state`, or `chip8_reset` followed by
+  // `chip8_load_rom`, starts it again.
+  CHIP8_STATUS_HALTED = 5,
+  // A bug in the emulator. The machine should be freed.
+  CHIP8_STATUS_PANIC = 6,
+} Chip8Status;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a machine whose random numbers come from `seed`. Returns null if
+// it could not be created. Free it with `chip8_free`.
+Chip8 *chip8_new(uint64_t seed);
+
+// Frees a machine from `chip8_new`. Null is ignored.
+//
+// # Safety
+//
+// `chip8` must be null or a machine from `chip8_new` that has not been freed.
+void chip8_free(Chip8 *chip8);
+
+// Copies `len` bytes of ROM into memory at 0x200. Known ROMs get the quirks
+// and speed recorded in the ROM database, and others the quirks of the
+// platform they most likely target.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`, and `rom` must point to `len` readable
+// bytes unless `len` is 0.
+enum Chip8Status chip8_load_rom(Chip8 *chip8, const uint8_t *rom, size_t len);
+
+// Clears the screen, the stack, the timers and memory from 0x200, and
+// unloads the ROM. V0-VF keep their values. Load a ROM again with
+// `chip8_load_rom` before running the machine.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+enum Chip8Status chip8_reset(Chip8 *chip8);
+
+// Runs one 60Hz frame. Returns `CHIP8_STATUS_HALTED` once the program has
+// hit an error.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+enum Chip8Status chip8_run_frame(Chip8 *chip8);
+
+// Presses or releases `key`, 0x0-0xF.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+enum Chip8Status chip8_set_key(Chip8 *chip8, uint8_t key, bool pressed);
+
+// Copies the screen into `out`, `CHIP8_SCREEN_SIZE` bytes of 0 or 1 row by
+// row.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`, and `out` must point to `len`
+// writable bytes unless `len` is 0.
+enum Chip8Status chip8_screen(const Chip8 *chip8, uint8_t *out, size_t len);
+
+// Saves the machine's state into `out` for `chip8_load_state` and stores
+// its size in `written`. If `len` is too small, nothing is copied and
+// `CHIP8_STATUS_BUFFER_TOO_SMALL` is returned, so calling with a null `out`
+// and a `len` of 0 asks for the size.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`, `out` must point to `len` writable
+// bytes unless `len` is 0, and `written` must be writable.
+enum Chip8Status chip8_save_state(const Chip8 *chip8, uint8_t *out, size_t len, size_t *written);
+
+// Restores a state from `chip8_save_state`. The machine is left alone if the
+// bytes are not a state this version can read.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`, and `state` must point to `len`
+// readable bytes unless `len` is 0.
+enum Chip8Status chip8_load_state(Chip8 *chip8, const uint8_t *state, size_t len);
+
+// A short description of `status`, valid for the life of the program.
+// Values that are not a `Chip8Status` get "unknown status".
+const char *chip8_status_message(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,677 @@
+// Static analysis of ROM images. Starting at the entry point, instructions are
+// followed through jumps, calls, skips and returns to recover a control-flow
+// graph, which in turn tells code apart from sprite and other data.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{get_kk, get_nnn, get_x, get_y, REG_MAX, START_OF_ROM};
+
+const ALL_KEYS: u16 = 0xFFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    // Execution continues with the next instruction
+    Fallthrough,
+    // 1nnn
+    Jump,
+    // 2nnn; the matching return lands on the call's fallthrough edge
+    Call,
+    // The instruction after a taken 3xkk/4xkk/5xy0/9xy0/Ex9E/ExA1
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteKind {
+    Code,
+    // Read by Dxyn after being pointed at by Annn
+    Sprite,
+    // Pointed at by Annn but not drawn directly, e.g. tables for Fx65
+    Data,
+    // Never reached or referenced
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock {
+    pub start: usize,
+    // (address, opcode) for each instruction, in order
+    pub instructions: Vec<(usize, u16)>,
+    pub successors: Vec<Edge>,
+    // Ends in Bnnn, whose target depends on a register at run time
+    pub computed_jump: bool,
+    // Ends in 00EE
+    pub returns: bool,
+}
+
+impl BasicBlock {
+    // First address past the block
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |&(address, _)| address + 2)
+    }
+}
+
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    // Classification of every ROM byte, indexed from the start of the ROM
+    pub bytes: Vec<ByteKind>,
+    // Addresses of Bnnn instructions whose targets could not be resolved
+    pub unresolved_jumps: Vec<usize>,
+    // Entry points of subroutines reached through 2nnn
+    pub subroutines: BTreeSet<usize>,
+}
+
+struct Decoded {
+    // Size in bytes; XO-CHIP's F000 nnnn carries a second word
+    len: usize,
+    successors: Vec<Edge>,
+    ends_block: bool,
+    computed_jump: bool,
+    returns: bool,
+}
+
+fn instruction_len(opcode: u16) -> usize {
+    if opcode == 0xF000 {
+        4
+    } else {
+        2
+    }
+}
+
+fn decode_flow(rom: &[u8], address: usize, opcode: u16) -> Decoded {
+    let len = instruction_len(opcode);
+    let next = address + len;
+    let nnn = get_nnn(opcode) as usize;
+    let fallthrough = Edge {
+        target: next,
+        kind: EdgeKind::Fallthrough,
+    };
+    // Skips step over a whole instruction, including a long load
+    let skipped_len = read_opcode(rom, next).map_or(2, instruction_len);
+    let skip = Edge {
+        target: next + skipped_len,
+        kind: EdgeKind::Skip,
+    };
+
+    let mut decoded = Decoded {
+        len,
+        successors: vec![fallthrough],
+        ends_block: false,
+        computed_jump: false,
+        returns: false,
+    };
+    match opcode & 0xF000 {
+        0x0000 if opcode == 0x00EE => {
+            decoded.successors.clear();
+            decoded.ends_block = true;
+            decoded.returns = true;
+        }
+        // SUPER-CHIP exit
+        0x0000 if opcode == 0x00FD => {
+            decoded.successors.clear();
+            decoded.ends_block = true;
+        }
+        0x1000 => {
+            decoded.successors = vec![Edge {
+                target: nnn,
+                kind: EdgeKind::Jump,
+            }];
+            decoded.ends_block = true;
+        }
+        0x2000 => {
+            decoded.successors.push(Edge {
+                target: nnn,
+                kind: EdgeKind::Call,
+            });
+            decoded.ends_block = true;
+        }
+        0x3000 | 0x4000 => {
+            decoded.successors.push(skip);
+            decoded.ends_block = true;
+        }
+        // 5xy2/5xy3 are XO-CHIP register range stores, not skips
+        0x5000 | 0x9000 if opcode & 0xF == 0 => {
+            decoded.successors.push(skip);
+            decoded.ends_block = true;
+        }
+        0xB000 => {
+            decoded.successors.clear();
+            decoded.ends_block = true;
+            decoded.computed_jump = true;
+        }
+        0xE000 if matches!(opcode & 0xFF, 0x9E | 0xA1) => {
+            decoded.successors.push(skip);
+            decoded.ends_block = true;
+        }
+        _ => {}
+    }
+    decoded
+}
+
+fn read_opcode(rom: &[u8], address: usize) -> Option<u16> {
+    let offset = address.checked_sub(START_OF_ROM)?;
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+    Some((rom[offset] as u16) << 8 | rom[offset + 1] as u16)
+}
+
+impl ControlFlowGraph {
+    pub fn build(rom: &[u8]) -> Self {
+        // Pass 1: find every reachable instruction and every block leader
+        let mut reachable = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut subroutines = BTreeSet::new();
+        let mut worklist = vec![START_OF_ROM];
+        leaders.insert(START_OF_ROM);
+
+        while let Some(address) = worklist.pop() {
+            if reachable.contains_key(&address) {
+                continue;
+            }
+            let opcode = match read_opcode(rom, address) {
+                Some(opcode) => opcode,
+                None => continue,
+            };
+            let decoded = decode_flow(rom, address, opcode);
+            for edge in &decoded.successors {
+                if edge.kind != EdgeKind::Fallthrough || decoded.ends_block {
+                    leaders.insert(edge.target);
+                }
+                if edge.kind == EdgeKind::Call {
+                    subroutines.insert(edge.target);
+                }
+                worklist.push(edge.target);
+            }
+            reachable.insert(address, (opcode, decoded));
+        }
+
+        // Pass 2: group instructions into blocks that end at a branch or
+        // just before the next leader
+        let mut blocks = BTreeMap::new();
+        let mut unresolved_jumps = Vec::new();
+        for &leader in &leaders {
+            if !reachable.contains_key(&leader) {
+                continue;
+            }
+            let mut block = BasicBlock {
+                start: leader,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+                computed_jump: false,
+                returns: false,
+            };
+            let mut address = leader;
+            while let Some((opcode, decoded)) = reachable.get(&address) {
+                block.instructions.push((address, *opcode));
+                let next = address + decoded.len;
+                if decoded.ends_block || leaders.contains(&next) {
+                    block.successors = decoded.successors.clone();
+                    block.computed_jump = decoded.computed_jump;
+                    block.returns = decoded.returns;
+                    if decoded.computed_jump {
+                        unresolved_jumps.push(address);
+                    }
+                    break;
+                }
+                address = next;
+            }
+            // Edges that lead outside the ROM are dropped
+            block
+                .successors
+                .retain(|edge| reachable.contains_key(&edge.target));
+            blocks.insert(leader, block);
+        }
+
+        let bytes = classify_bytes(rom, &reachable);
+        ControlFlowGraph {
+            blocks,
+            bytes,
+            unresolved_jumps,
+            subroutines,
+        }
+    }
+
+    pub fn is_code(&self, address: usize) -> bool {
+        self.byte_kind(address) == Some(ByteKind::Code)
+    }
+
+    pub fn byte_kind(&self, address: usize) -> Option<ByteKind> {
+        address
+            .checked_sub(START_OF_ROM)
+            .and_then(|offset| self.bytes.get(offset).copied())
+    }
+
+    // Graphviz rendering: one node per block labelled with its disassembly
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph rom {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for &(address, opcode) in &block.instructions {
+                let _ = write!(label, "{:03X}: {}\\l", address, disassemble(opcode));
+            }
+            let style = if self.subroutines.contains(&block.start) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    b{:03X} [label=\"{}\"{}];",
+                block.start, label, style
+            );
+
+            for edge in &block.successors {
+                let attributes = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jp\"]",
+                    EdgeKind::Call => " [label=\"call\", color=blue]",
+                    EdgeKind::Skip => " [label=\"skip\", style=dashed]",
+                };
+                let _ = writeln!(
+                    dot,
+                    "    b{:03X} -> b{:03X}{};",
+                    block.start, edge.target, attributes
+                );
+            }
+            if block.computed_jump {
+                let _ = writeln!(
+                    dot,
+                    "    u{0:03X} [label=\"?\", shape=circle];\n    b{0:03X} -> u{0:03X} [style=dotted];",
+                    block.start
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Listing of the whole ROM: mnemonics for code, `db` rows for everything else
+    pub fn disassembly(&self, rom: &[u8]) -> String {
+        let mut out = String::new();
+        let mut offset = 0;
+        while offset < rom.len() {
+            let address = START_OF_ROM + offset;
+            if self.blocks.contains_key(&address) {
+                let _ = writeln!(out, "\nblock_{:03X}:", address);
+            }
+
+            if self.bytes[offset] == ByteKind::Code && offset + 1 < rom.len() {
+                let opcode = (rom[offset] as u16) << 8 | rom[offset + 1] as u16;
+                match read_opcode(rom, address + 2) {
+                    Some(operand) if opcode == 0xF000 => {
+                        let _ = writeln!(
+                            out,
+                            "{:03X}: {:04X} {:04X}  LD I, long 0x{:04X}",
+                            address, opcode, operand, operand
+                        );
+                        offset += 4;
+                    }
+                    _ => {
+                        let _ = writeln!(
+                            out,
+                            "{:03X}: {:04X}  {}",
+                            address,
+                            opcode,
+                            disassemble(opcode)
+                        );
+                        offset += 2;
+                    }
+                }
+                continue;
+            }
+
+            // Group a run of bytes of the same kind, at most 8 per line
+            let kind = self.bytes[offset];
+            let run = self.bytes[offset..]
+                .iter()
+                .take(8)
+                .take_while(|&&k| k == kind && k != ByteKind::Code)
+                .count()
+                .max(1);
+            let values: Vec<String> = rom[offset..offset + run]
+                .iter()
+                .map(|byte| format!("0x{:02X}", byte))
+                .collect();
+            let comment = match kind {
+                ByteKind::Sprite => "  ; sprite",
+                ByteKind::Data => "  ; data",
+                _ => "",
+            };
+            let _ = writeln!(out, "{:03X}: db {}{}", address, values.join(", "), comment);
+            offset += run;
+        }
+        out
+    }
+
+    // Keys the program tests with Ex9E/ExA1, one bit per key. Register values
+    // are followed through constant loads, adds and copies across blocks and
+    // calls. A test of a register that could hold anything, a computed jump
+    // hiding code, or an Fx0A with no key tests at all counts as every key.
+    pub fn keys_read(&self) -> u16 {
+        if !self.unresolved_jumps.is_empty() {
+            return ALL_KEYS;
+        }
+        let owners = self.return_owners();
+        // Registers on entry to each block, the join of every path seen so far
+        let mut entry: BTreeMap<usize, Registers> = BTreeMap::new();
+        // Per subroutine: registers at its returns, and where its calls return to
+        let mut exits: BTreeMap<usize, Registers> = BTreeMap::new();
+        let mut return_sites: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut worklist = Vec::new();
+        // Registers start out cleared
+        merge_into(&mut entry, &mut worklist, START_OF_ROM, &[Some(0); REG_MAX]);
+
+        let mut keys = 0;
+        let mut waits_for_key = false;
+        while let Some(start) = worklist.pop() {
+            let block = &self.blocks[&start];
+            let mut regs = entry[&start];
+            for &(_, opcode) in &block.instructions {
+                match opcode & 0xF0FF {
+                    0xE09E | 0xE0A1 => {
+                        keys |= regs[get_x(opcode)].map_or(ALL_KEYS, |key| 1 << (key & 0xF))
+                    }
+                    0xF00A => waits_for_key = true,
+                    _ => {}
+                }
+                propagate_constants(&mut regs, opcode);
+            }
+
+            let call = block
+                .successors
+                .iter()
+                .find(|edge| edge.kind == EdgeKind::Call)
+                .map(|edge| edge.target);
+            for edge in &block.successors {
+                match (edge.kind, call) {
+                    // Reached through the callee's returns instead
+                    (EdgeKind::Fallthrough, Some(callee)) => {
+                        return_sites.entry(callee).or_default().insert(edge.target);
+                        if let Some(exit) = exits.get(&callee) {
+                            merge_into(&mut entry, &mut worklist, edge.target, exit);
+                        }
+                    }
+                    _ => merge_into(&mut entry, &mut worklist, edge.target, &regs),
+                }
+            }
+            if block.returns {
+                for &subroutine in owners.get(&start).into_iter().flatten() {
+                    let changed = match exits.get_mut(&subroutine) {
+                        Some(exit) => join(exit, &regs),
+                        None => {
+                            exits.insert(subroutine, regs);
+                            true
+                        }
+                    };
+                    if changed {
+                        let exit = exits[&subroutine];
+                        for &site in return_sites.get(&subroutine).into_iter().flatten() {
+                            merge_into(&mut entry, &mut worklist, site, &exit);
+                        }
+                    }
+                }
+            }
+        }
+
+        if keys == 0 && waits_for_key {
+            ALL_KEYS
+        } else {
+            keys
+        }
+    }
+
+    // Subroutines each returning block belongs to, found by walking every
+    // subroutine body and stepping over the calls it makes
+    fn return_owners(&self) -> BTreeMap<usize, Vec<usize>> {
+        let mut owners: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &subroutine in &self.subroutines {
+            let mut seen = BTreeSet::new();
+            let mut worklist = vec![subroutine];
+            while let Some(start) = worklist.pop() {
+                let block = match self.blocks.get(&start) {
+                    Some(block) if seen.insert(start) => block,
+                    _ => continue,
+                };
+                if block.returns {
+                    owners.entry(start).or_default().push(subroutine);
+                }
+                worklist.extend(
+                    block
+                        .successors
+                        .iter()
+                        .filter(|edge| edge.kind != EdgeKind::Call)
+                        .map(|edge| edge.target),
+                );
+            }
+        }
+        owners
+    }
+}
+
+// What is known about each register: Some(value) when every path agrees on a
+// constant, None when it could hold anything
+type Registers = [Option<u8>; REG_MAX];
+
+// Widens `into` to cover `from`; true if anything changed
+fn join(into: &mut Registers, from: &Registers) -> bool {
+    let mut changed = false;
+    for (into, from) in into.iter_mut().zip(from) {
+        if into.is_some() && into != from {
+            *into = None;
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn merge_into(
+    entry: &mut BTreeMap<usize, Registers>,
+    worklist: &mut Vec<usize>,
+    target: usize,
+    regs: &Registers,
+) {
+    let changed = match entry.get_mut(&target) {
+        Some(known) => join(known, regs),
+        None => {
+            entry.insert(target, *regs);
+            true
+        }
+    };
+    if changed {
+        worklist.push(target);
+    }
+}
+
+// Applies one instruction's register writes
+fn propagate_constants(regs: &mut Registers, opcode: u16) {
+    let x = get_x(opcode);
+    let y = get_y(opcode);
+    let kk = get_kk(opcode);
+    match opcode & 0xF000 {
+        0x6000 => regs[x] = Some(kk),
+        0x7000 => regs[x] = regs[x].map(|value| value.wrapping_add(kk)),
+        0x8000 if opcode & 0xF == 0 => regs[x] = regs[y],
+        0x8000 => {
+            regs[x] = None;
+            regs[0xF] = None;
+        }
+        0xC000 => regs[x] = None,
+        0xD000 => regs[0xF] = None,
+        // XO-CHIP range load
+        0x5000 if opcode & 0xF == 3 => {
+            for reg in &mut regs[x.min(y)..=x.max(y)] {
+                *reg = None;
+            }
+        }
+        0xF000 => match kk {
+            0x07 | 0x0A => regs[x] = None,
+            0x65 | 0x85 => {
+                for reg in &mut regs[..=x] {
+                    *reg = None;
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// Marks reachable instructions as code, then follows Annn targets: bytes a
+// later Dxyn in the same straight-line run draws become sprites, and other
+// referenced bytes become data
+fn classify_bytes(rom: &[u8], reachable: &BTreeMap<usize, (u16, Decoded)>) -> Vec<ByteKind> {
+    let mut bytes = vec![ByteKind::Unknown; rom.len()];
+
+    for (&address, (_, decoded)) in reachable {
+        let offset = address - START_OF_ROM;
+        for byte in bytes.iter_mut().skip(offset).take(decoded.len) {
+            *byte = ByteKind::Code;
+        }
+    }
+
+    for (&address, (opcode, _)) in reachable {
+        if opcode & 0xF000 != 0xA000 {
+            continue;
+        }
+        let target = get_nnn(*opcode) as usize;
+
+        // Look ahead for the instruction that uses I, stepping over calls and
+        // skips since both come back to the next instruction
+        let mut next = address + 2;
+        let mut kind = (ByteKind::Data, 1);
+        while let Some((opcode, decoded)) = reachable.get(&next) {
+            match opcode & 0xF000 {
+                0xA000 => break,
+                0xD000 => {
+                    kind = (ByteKind::Sprite, (opcode & 0xF) as usize);
+                    break;
+                }
+                0xF000 if matches!(opcode & 0xFF, 0x55 | 0x65) => {
+                    kind = (ByteKind::Data, get_x(*opcode) + 1);
+                    break;
+                }
+                0xF000 if opcode & 0xFF == 0x33 => {
+                    kind = (ByteKind::Data, 3);
+                    break;
+                }
+                _ => {}
+            }
+            let falls_through = decoded
+                .successors
+                .iter()
+                .any(|edge| edge.kind == EdgeKind::Fallthrough || edge.kind == EdgeKind::Call);
+            if !falls_through {
+                break;
+            }
+            next += decoded.len;
+        }
+        mark_referenced(&mut bytes, target, kind.1, kind.0);
+    }
+    bytes
+}
+
+// Code is never overwritten, and sprite wins over plain data
+fn mark_referenced(bytes: &mut [ByteKind], address: usize, len: usize, kind: ByteKind) {
+    let start = match address.checked_sub(START_OF_ROM) {
+        Some(start) => start,
+        None => return,
+    };
+    for byte in bytes.iter_mut().skip(start).take(len) {
+        if *byte == ByteKind::Unknown || (*byte == ByteKind::Data && kind == ByteKind::Sprite) {
+            *byte = kind;
+        }
+    }
+}
+
+// Cowgod-style mnemonic for a single instruction, with the SUPER-CHIP and
+// XO-CHIP extensions. The operand of F000 nnnn is not part of `opcode`.
+pub fn disassemble(opcode: u16) -> String {
+    let x = get_x(opcode);
+    let y = get_y(opcode);
+    let kk = get_kk(opcode);
+    let nnn = get_nnn(opcode);
+    let n = opcode & 0xF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            0x00FB => "SCR".to_string(),
+            0x00FC => "SCL".to_string(),
+            0x00FD => "EXIT".to_string(),
+            0x00FE => "LOW".to_string(),
+            0x00FF => "HIGH".to_string(),
+            _ if opcode & 0xFFF0 == 0x00C0 => format!("SCD {}", n),
+            _ if opcode & 0xFFF0 == 0x00D0 => format!("SCU {}", n),
+            _ => format!("SYS 0x{:03X}", nnn),
+        },
+        0x1000 => format!("JP 0x{:03X}", nnn),
+        0x2000 => format!("CALL 0x{:03X}", nnn),
+        0x3000 => format!("SE V{:X}, 0x{:02X}", x, kk),
+        0x4000 => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x5000 if n == 2 => format!("SAVE V{:X} - V{:X}", x, y),
+        0x5000 if n == 3 => format!("LOAD V{:X} - V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, 0x{:02X}", x, kk),
+        0x7000 => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => format!("DW 0x{:04X}", opcode),
+        },
+        0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, 0x{:03X}", nnn),
+        0xB000 => format!("JP V0, 0x{:03X}", nnn),
+        0xC000 => format!("RND V{:X}, 0x{:02X}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 if kk == 0x9E => format!("SKP V{:X}", x),
+        0xE000 if kk == 0xA1 => format!("SKNP V{:X}", x),
+        0xF000 if opcode == 0xF000 => "LD I, long".to_string(),
+        0xF000 if opcode == 0xF002 => "AUDIO".to_string(),
+        0xF000 => match kk {
+            0x01 => format!("PLANE {}", x),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => format!("DW 0x{:04X}", opcode),
+        },
+        _ => format!("DW 0x{:04X}", opcode),
+    }
+}
+
+#[wasm_bindgen]
+pub fn rom_to_dot(rom: &[u8]) -> String {
+    ControlFlowGraph::build(rom).to_dot()
+}
+
+#[wasm_bindgen]
+pub fn disassemble_rom(rom: &[u8]) -> String {
+    ControlFlowGraph::build(rom).disassembly(rom)
+}
@@ -0,0 +1,140 @@
+// Audio capture: renders the buzzer to 16-bit mono PCM and encodes it as a
+// WAV file, one block of samples per emulated 60Hz frame.
+use wasm_bindgen::prelude::*;
+
+use crate::Chip8;
+
+const FRAMES_PER_SECOND: u32 = 60;
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+// Matches the tone the web frontend plays
+const DEFAULT_FREQUENCY: f32 = 400.0;
+const DEFAULT_VOLUME: f32 = 0.5;
+
+const BITS_PER_SAMPLE: u16 = 16;
+const CHANNELS: u16 = 1;
+const WAV_HEADER_SIZE: usize = 44;
+
+#[wasm_bindgen]
+pub struct WavRecorder {
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+
+    samples: Vec<i16>,
+    frames: u32,
+    beep_frames: u32,
+    // Oscillator phase in [0, 1), carried across frames so the tone is continuous
+    phase: f32,
+}
+
+impl Default for WavRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WavRecorder {
+    pub fn new() -> Self {
+        Self::with_format(DEFAULT_SAMPLE_RATE, DEFAULT_FREQUENCY, DEFAULT_VOLUME)
+    }
+
+    pub fn with_format(sample_rate: u32, frequency: f32, volume: f32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            frequency,
+            volume: volume.clamp(0.0, 1.0),
+            samples: Vec::new(),
+            frames: 0,
+            beep_frames: 0,
+            phase: 0.0,
+        }
+    }
+
+    // Call once per emulated frame, after `Chip8::run_frame`
+    pub fn capture(&mut self, chip8: &Chip8) {
+        self.push_frame(chip8.is_beeping());
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.frames = 0;
+        self.beep_frames = 0;
+        self.phase = 0.0;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    // Number of captured frames during which the buzzer was on
+    pub fn beep_frames(&self) -> u32 {
+        self.beep_frames
+    }
+
+    pub fn encode_wav(&self) -> Vec<u8> {
+        let data_size = self.samples.len() * 2;
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        let mut out = Vec::with_capacity(WAV_HEADER_SIZE + data_size);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&((WAV_HEADER_SIZE - 8 + data_size) as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        out.extend_from_slice(&CHANNELS.to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&byte_rate.to_le_bytes());
+        out.extend_from_slice(&block_align.to_le_bytes());
+        out.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data_size as u32).to_le_bytes());
+        for sample in &self.samples {
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        out
+    }
+}
+
+impl WavRecorder {
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn push_frame(&mut self, is_beeping: bool) {
+        // Frame boundaries are computed from the running frame count so that
+        // rates which are not multiples of 60 never drift
+        let start = self.frame_to_sample(self.frames);
+        self.frames += 1;
+        let end = self.frame_to_sample(self.frames);
+
+        if !is_beeping {
+            self.samples.extend(std::iter::repeat_n(0, end - start));
+            return;
+        }
+
+        self.beep_frames += 1;
+        let amplitude = (self.volume * i16::MAX as f32) as i16;
+        let step = self.frequency / self.sample_rate as f32;
+        for _ in start..end {
+            self.samples.push(if self.phase < 0.5 {
+                amplitude
+            } else {
+                -amplitude
+            });
+            self.phase = (self.phase + step).fract();
+        }
+    }
+
+    fn frame_to_sample(&self, frame: u32) -> usize {
+        (frame as u64 * self.sample_rate as u64 / FRAMES_PER_SECOND as u64) as usize
+    }
+}
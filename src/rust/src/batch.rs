@@ -0,0 +1,385 @@
+// Many independent machines stepped together, for reinforcement learning and
+// search where driving one `Chip8` at a time from the host is the bottleneck.
+//
+// The batch is a struct of arrays: every field a program changes, and every
+// input and output, is one array across all machines. Memory, registers, I,
+// the program counter and the timers sit next to key masks, seeds, screens,
+// packed display rows and status flags, so a host can hand over every action
+// and read back every observation in one go. Each `Chip8` holds what only the
+// core needs, its settings, code caches and random number generator, runs a
+// step on its own copy of the state and writes back what the step changed.
+// A batch behaves exactly like the same number of separate machines.
+use rand::SeedableRng;
+use wasm_bindgen::prelude::*;
+
+use crate::rng::Xoshiro256PlusPlus;
+use crate::{
+    Chip8, DisplayRow, ExecutionEngine, Pixel, Quirks, FRAME_BUF_HEIGHT, FRAME_BUF_MAX, KEY_COUNT,
+    MEM_MAX, REG_MAX,
+};
+
+#[wasm_bindgen]
+pub struct Chip8Batch {
+    machines: Vec<Chip8>,
+    // State every machine returns to on `reset`, taken right after loading
+    initial_state: Vec<u8>,
+    seeds: Vec<u64>,
+    lanes: Lanes,
+}
+
+// One array per field, machine after machine
+struct Lanes {
+    // Keys held by each machine during the next step, one bit per key
+    keys: Vec<u16>,
+    memory: Vec<u8>,
+    registers: Vec<u8>,
+    index_registers: Vec<u16>,
+    program_counters: Vec<usize>,
+    delay_timers: Vec<u8>,
+    sound_timers: Vec<u8>,
+    frame_buffers: Vec<Pixel>,
+    display_rows: Vec<DisplayRow>,
+    beeping: Vec<bool>,
+    halted: Vec<bool>,
+}
+
+// One machine's entries in `Lanes`
+struct Lane<'a> {
+    keys: u16,
+    memory: &'a mut [u8],
+    registers: &'a mut [u8],
+    index_register: &'a mut u16,
+    program_counter: &'a mut usize,
+    delay_timer: &'a mut u8,
+    sound_timer: &'a mut u8,
+    frame_buffer: &'a mut [Pixel],
+    display_rows: &'a mut [DisplayRow],
+    beeping: &'a mut bool,
+    halted: &'a mut bool,
+}
+
+impl Lanes {
+    fn new(count: usize) -> Self {
+        Lanes {
+            keys: vec![0; count],
+            memory: vec![0; count * MEM_MAX],
+            registers: vec![0; count * REG_MAX],
+            index_registers: vec![0; count],
+            program_counters: vec![0; count],
+            delay_timers: vec![0; count],
+            sound_timers: vec![0; count],
+            frame_buffers: vec![0; count * FRAME_BUF_MAX],
+            display_rows: vec![0; count * FRAME_BUF_HEIGHT],
+            beeping: vec![false; count],
+            halted: vec![false; count],
+        }
+    }
+
+    fn lane(&mut self, index: usize) -> Lane<'_> {
+        Lane {
+            keys: self.keys[index],
+            memory: &mut self.memory[index * MEM_MAX..(index + 1) * MEM_MAX],
+            registers: &mut self.registers[index * REG_MAX..(index + 1) * REG_MAX],
+            index_register: &mut self.index_registers[index],
+            program_counter: &mut self.program_counters[index],
+            delay_timer: &mut self.delay_timers[index],
+            sound_timer: &mut self.sound_timers[index],
+            frame_buffer: &mut self.frame_buffers
+                [index * FRAME_BUF_MAX..(index + 1) * FRAME_BUF_MAX],
+            display_rows: &mut self.display_rows
+                [index * FRAME_BUF_HEIGHT..(index + 1) * FRAME_BUF_HEIGHT],
+            beeping: &mut self.beeping[index],
+            halted: &mut self.halted[index],
+        }
+    }
+
+    fn lanes(&mut self) -> Vec<Lane<'_>> {
+        let mut memory = self.memory.chunks_mut(MEM_MAX);
+        let mut registers = self.registers.chunks_mut(REG_MAX);
+        let mut index_registers = self.index_registers.iter_mut();
+        let mut program_counters = self.program_counters.iter_mut();
+        let mut delay_timers = self.delay_timers.iter_mut();
+        let mut sound_timers = self.sound_timers.iter_mut();
+        let mut frame_buffers = self.frame_buffers.chunks_mut(FRAME_BUF_MAX);
+        let mut display_rows = self.display_rows.chunks_mut(FRAME_BUF_HEIGHT);
+        let mut beeping = self.beeping.iter_mut();
+        let mut halted = self.halted.iter_mut();
+        self.keys
+            .iter()
+            .map(|&keys| Lane {
+                keys,
+                memory: memory.next().unwrap(),
+                registers: registers.next().unwrap(),
+                index_register: index_registers.next().unwrap(),
+                program_counter: program_counters.next().unwrap(),
+                delay_timer: delay_timers.next().unwrap(),
+                sound_timer: sound_timers.next().unwrap(),
+                frame_buffer: frame_buffers.next().unwrap(),
+                display_rows: display_rows.next().unwrap(),
+                beeping: beeping.next().unwrap(),
+                halted: halted.next().unwrap(),
+            })
+            .collect()
+    }
+}
+
+impl Lane<'_> {
+    // Copies the machine's state in, memory only where it changed unless
+    // `all_memory` is set
+    fn copy_from(&mut self, machine: &mut Chip8, all_memory: bool) {
+        let (start, end) = if all_memory {
+            (0, MEM_MAX)
+        } else {
+            machine.memory_written
+        };
+        if start < end {
+            self.memory[start..end].copy_from_slice(&machine.memory[start..end]);
+        }
+        machine.memory_written = (MEM_MAX, 0);
+        self.registers.copy_from_slice(&machine.reg);
+        *self.index_register = machine.index_reg;
+        *self.program_counter = machine.program_counter;
+        *self.delay_timer = machine.delay_timer;
+        *self.sound_timer = machine.sound_timer;
+        self.frame_buffer.copy_from_slice(&machine.frame_buffer);
+        self.display_rows.copy_from_slice(&machine.display_rows);
+        *self.beeping = machine.is_beeping;
+        *self.halted = machine.error.is_some();
+    }
+
+    fn step(&mut self, machine: &mut Chip8) {
+        apply_keys(machine, self.keys);
+        machine.run_frame();
+        self.copy_from(machine, false);
+    }
+}
+
+#[wasm_bindgen]
+impl Chip8Batch {
+    // One machine per seed
+    pub fn new(seeds: &[u64]) -> Self {
+        let mut batch = Chip8Batch {
+            machines: seeds.iter().map(|&seed| Chip8::with_seed(seed)).collect(),
+            initial_state: Vec::new(),
+            seeds: seeds.to_vec(),
+            lanes: Lanes::new(seeds.len()),
+        };
+        batch.copy_all();
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    // Loads `rom` into every machine and resets them all to their seeds. What
+    // the last ROM left behind is cleared first, since machine 0's state is
+    // copied to the rest.
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        for machine in &mut self.machines {
+            // `Chip8::reset` keeps the registers
+            machine.reset();
+            machine.reg = [0; REG_MAX];
+            machine.load_rom(rom);
+        }
+        self.initial_state = match self.machines.first() {
+            Some(machine) => machine.save_state(),
+            None => Vec::new(),
+        };
+        for index in 0..self.len() {
+            self.reset(index, self.seeds[index]);
+        }
+    }
+
+    // Puts machine `index` back to where it was right after `load_rom`, with
+    // no keys held and a new seed. False if there is no such machine.
+    pub fn reset(&mut self, index: usize, seed: u64) -> bool {
+        let machine = match self.machines.get_mut(index) {
+            Some(machine) => machine,
+            None => return false,
+        };
+        if !self.initial_state.is_empty() {
+            machine.load_state(&self.initial_state);
+        }
+        machine.rand_rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        self.seeds[index] = seed;
+        self.lanes.keys[index] = 0;
+        self.lanes.lane(index).copy_from(machine, true);
+        true
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        for machine in &mut self.machines {
+            machine.set_quirks(quirks);
+        }
+    }
+
+    pub fn set_instructions_per_second(&mut self, instructions: u32) {
+        for machine in &mut self.machines {
+            machine.set_instructions_per_second(instructions);
+        }
+    }
+
+    pub fn set_execution_engine(&mut self, engine: ExecutionEngine) {
+        for machine in &mut self.machines {
+            machine.set_execution_engine(engine);
+        }
+    }
+
+    // None past the last machine, as for every per-machine getter
+    pub fn get_seed(&self, index: usize) -> Option<u64> {
+        self.seeds.get(index).copied()
+    }
+
+    pub fn get_keys(&self, index: usize) -> Option<u16> {
+        self.lanes.keys.get(index).copied()
+    }
+
+    // Keys machine `index` holds from the next step on, one bit per key.
+    // False if there is no such machine.
+    pub fn set_keys(&mut self, index: usize, keys: u16) -> bool {
+        match self.lanes.keys.get_mut(index) {
+            Some(held) => {
+                *held = keys;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Runs one frame on every machine
+    pub fn step(&mut self) {
+        for (machine, mut lane) in self.machines.iter_mut().zip(self.lanes.lanes()) {
+            lane.step(machine);
+        }
+    }
+
+    // Every machine's screen after the last step, `FRAME_BUF_MAX` bytes each
+    pub fn get_frame_buffers(&self) -> *const Pixel {
+        self.lanes.frame_buffers.as_ptr()
+    }
+
+    // Every machine's memory, `MEM_MAX` bytes each
+    pub fn get_memory(&self) -> *const u8 {
+        self.lanes.memory.as_ptr()
+    }
+
+    // Every machine's V0-VF, `REG_MAX` bytes each
+    pub fn get_registers(&self) -> *const u8 {
+        self.lanes.registers.as_ptr()
+    }
+
+    pub fn is_beeping(&self, index: usize) -> Option<bool> {
+        self.lanes.beeping.get(index).copied()
+    }
+
+    // Whether machine `index` has hit an error and stopped
+    pub fn is_halted(&self, index: usize) -> Option<bool> {
+        self.lanes.halted.get(index).copied()
+    }
+}
+
+// Native-only API, not exported to JavaScript
+impl Chip8Batch {
+    // Sets every machine's keys at once, one mask per machine. False, and
+    // nothing changes, unless there is exactly one mask per machine.
+    pub fn set_all_keys(&mut self, keys: &[u16]) -> bool {
+        if keys.len() != self.len() {
+            return false;
+        }
+        self.lanes.keys.copy_from_slice(keys);
+        true
+    }
+
+    pub fn frame_buffers(&self) -> &[Pixel] {
+        &self.lanes.frame_buffers
+    }
+
+    // `FRAME_BUF_HEIGHT` packed rows per machine, as in `Chip8::display_rows`
+    pub fn display_rows(&self) -> &[DisplayRow] {
+        &self.lanes.display_rows
+    }
+
+    // `MEM_MAX` bytes per machine, as in `Chip8::memory`
+    pub fn memory(&self) -> &[u8] {
+        &self.lanes.memory
+    }
+
+    // `REG_MAX` registers per machine, as in `Chip8::registers`
+    pub fn registers(&self) -> &[u8] {
+        &self.lanes.registers
+    }
+
+    pub fn index_registers(&self) -> &[u16] {
+        &self.lanes.index_registers
+    }
+
+    pub fn program_counters(&self) -> &[usize] {
+        &self.lanes.program_counters
+    }
+
+    pub fn delay_timers(&self) -> &[u8] {
+        &self.lanes.delay_timers
+    }
+
+    pub fn sound_timers(&self) -> &[u8] {
+        &self.lanes.sound_timers
+    }
+
+    pub fn beeping(&self) -> &[bool] {
+        &self.lanes.beeping
+    }
+
+    pub fn halted(&self) -> &[bool] {
+        &self.lanes.halted
+    }
+
+    pub fn machines(&self) -> &[Chip8] {
+        &self.machines
+    }
+
+    // Like `step`, with the machines split over `threads` threads. The threads
+    // are started on every call, so this pays off for large batches or fast
+    // machines rather than small ones.
+    pub fn step_threaded(&mut self, threads: usize) {
+        let per_thread = self.len().div_ceil(threads.max(1)).max(1);
+        let machines = &mut self.machines;
+        let mut lanes = self.lanes.lanes();
+        std::thread::scope(|scope| {
+            for (machines, lanes) in machines
+                .chunks_mut(per_thread)
+                .zip(lanes.chunks_mut(per_thread))
+            {
+                scope.spawn(move || {
+                    for (machine, lane) in machines.iter_mut().zip(lanes) {
+                        lane.step(machine);
+                    }
+                });
+            }
+        });
+    }
+
+    fn copy_all(&mut self) {
+        for (machine, mut lane) in self.machines.iter_mut().zip(self.lanes.lanes()) {
+            lane.copy_from(machine, true);
+        }
+    }
+}
+
+// Presses and releases keys through `set_key` and `unset_key`, so a machine
+// waiting on Fx0A sees the press
+pub(crate) fn apply_keys(machine: &mut Chip8, keys: u16) {
+    for key in 0..KEY_COUNT as u8 {
+        let held = keys >> key & 1 != 0;
+        let was_held = machine.keys >> key & 1 != 0;
+        if held && !was_held {
+            machine.set_key(key);
+        } else if !held && was_held {
+            machine.unset_key(key);
+        }
+    }
+}
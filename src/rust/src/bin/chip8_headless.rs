@@ -0,0 +1,252 @@
+// Headless runner: executes a ROM for a fixed number of frames without a
+// browser, optionally recording the session to disk. It can also dump a static
+// analysis of the ROM instead of running it.
+//
+// Usage: chip8_headless <rom> [--frames N] [--record clip.gif|clip.png] [--scale N]
+//                       [--wav audio.wav] [--quirks PROFILE|auto] [--ips N] [--vip-timing]
+//                       [--cached-decode | --block-jit]
+//        chip8_headless <rom> --dot graph.dot | --disassemble
+//        chip8_headless --conformance <test_roms dir>
+use std::{env, fs, path::Path, process};
+
+use chip8_emulator::analysis::ControlFlowGraph;
+use chip8_emulator::conformance::{self, Outcome, SuiteStatus};
+use chip8_emulator::{Chip8, ExecutionEngine, Quirks, Recorder, TimingMode, WavRecorder};
+
+const DEFAULT_FRAMES: u32 = 600;
+const DEFAULT_SCALE: usize = 4;
+
+struct Options {
+    rom_path: String,
+    frames: u32,
+    record_path: Option<String>,
+    scale: usize,
+    wav_path: Option<String>,
+    quirks: Quirks,
+    auto_detect: bool,
+    instructions_per_second: Option<u32>,
+    timing_mode: TimingMode,
+    engine: ExecutionEngine,
+    conformance_dir: Option<String>,
+    dot_path: Option<String>,
+    disassemble: bool,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: chip8_headless <rom> [--frames N] [--record clip.gif|clip.png] [--scale N] \
+         [--wav audio.wav] [--quirks PROFILE|auto] [--ips N] [--vip-timing] [--cached-decode | --block-jit]\n       \
+         chip8_headless <rom> --dot graph.dot | --disassemble\n       \
+         chip8_headless --conformance <test_roms dir>"
+    );
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: DEFAULT_FRAMES,
+        record_path: None,
+        scale: DEFAULT_SCALE,
+        wav_path: None,
+        quirks: Quirks::default(),
+        auto_detect: false,
+        instructions_per_second: None,
+        timing_mode: TimingMode::Fixed,
+        engine: ExecutionEngine::Interpreter,
+        conformance_dir: None,
+        dot_path: None,
+        disassemble: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                options.frames = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--record" => options.record_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--scale" => {
+                options.scale = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--wav" => options.wav_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--quirks" => match args.next() {
+                Some(name) if name == "auto" => options.auto_detect = true,
+                name => {
+                    options.quirks = name
+                        .and_then(|name| Quirks::from_profile(&name))
+                        .unwrap_or_else(|| usage())
+                }
+            },
+            "--ips" => {
+                options.instructions_per_second = Some(
+                    args.next()
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--vip-timing" => options.timing_mode = TimingMode::CosmacVip,
+            "--cached-decode" => options.engine = ExecutionEngine::CachedDecode,
+            "--block-jit" => options.engine = ExecutionEngine::BlockJit,
+            "--conformance" => {
+                options.conformance_dir = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "--dot" => options.dot_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--disassemble" => options.disassemble = true,
+            _ if arg.starts_with("--") => usage(),
+            _ => options.rom_path = arg,
+        }
+    }
+
+    if options.rom_path.is_empty() && options.conformance_dir.is_none() {
+        usage();
+    }
+    options
+}
+
+// Prints one line per suite and profile; exits non-zero if anything that ran failed
+fn run_conformance(rom_dir: &str) -> ! {
+    let reports = conformance::run_all(|name| fs::read(Path::new(rom_dir).join(name)).ok());
+
+    let mut all_passed = true;
+    for report in &reports {
+        let summary = match &report.status {
+            SuiteStatus::RomMissing => "skipped (ROM not found)".to_string(),
+            SuiteStatus::Ran(results) => {
+                all_passed &= report.passed();
+                results
+                    .iter()
+                    .map(|result| {
+                        let mark = match result.outcome {
+                            Outcome::Pass => "ok",
+                            Outcome::Fail => "FAIL",
+                            Outcome::Unreadable => "??",
+                        };
+                        format!("{}:{}", result.label, mark)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        };
+        println!("{:<8} {:<8} {}", report.suite, report.profile, summary);
+    }
+    for (suite, rom) in conformance::PENDING_SUITES {
+        println!("{:<8} {:<8} not checked yet ({})", suite, "-", rom);
+    }
+    process::exit(if all_passed { 0 } else { 1 });
+}
+
+// Writes the control-flow graph and/or prints the disassembly, then exits
+fn analyze(rom: &[u8], options: &Options) -> ! {
+    let cfg = ControlFlowGraph::build(rom);
+    if let Some(path) = &options.dot_path {
+        if let Err(err) = fs::write(path, cfg.to_dot()) {
+            eprintln!("failed to write {}: {}", path, err);
+            process::exit(1);
+        }
+        println!(
+            "wrote {} ({} blocks, {} unresolved jumps)",
+            path,
+            cfg.blocks.len(),
+            cfg.unresolved_jumps.len()
+        );
+    }
+    if options.disassemble {
+        print!("{}", cfg.disassembly(rom));
+    }
+    process::exit(0);
+}
+
+fn main() {
+    let options = parse_args();
+    if let Some(dir) = &options.conformance_dir {
+        run_conformance(dir);
+    }
+
+    let rom = fs::read(&options.rom_path).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {}", options.rom_path, err);
+        process::exit(1);
+    });
+
+    if options.dot_path.is_some() || options.disassemble {
+        analyze(&rom, &options);
+    }
+
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(options.quirks);
+    chip8.set_auto_detect(options.auto_detect);
+    chip8.set_timing_mode(options.timing_mode);
+    chip8.set_execution_engine(options.engine);
+    chip8.load_rom(&rom);
+    // An explicit speed wins over the ROM database
+    if let Some(instructions) = options.instructions_per_second {
+        chip8.set_instructions_per_second(instructions);
+    }
+    if let Some(guess) = chip8.get_platform_guess() {
+        println!(
+            "detected {:?} ({:.0}% sure), running with {} quirks",
+            guess.platform,
+            guess.confidence * 100.0,
+            chip8
+                .get_quirks()
+                .profile_name()
+                .unwrap_or_else(|| "custom".to_string())
+        );
+    }
+
+    let mut recorder = options.record_path.as_ref().map(|_| Recorder::new());
+    let mut wav_recorder = options.wav_path.as_ref().map(|_| WavRecorder::new());
+    for _ in 0..options.frames {
+        chip8.run_frame();
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.capture(&chip8);
+        }
+        if let Some(wav_recorder) = wav_recorder.as_mut() {
+            wav_recorder.capture(&chip8);
+        }
+    }
+
+    if let (Some(path), Some(wav_recorder)) = (options.wav_path, wav_recorder) {
+        if let Err(err) = fs::write(&path, wav_recorder.encode_wav()) {
+            eprintln!("failed to write {}: {}", path, err);
+            process::exit(1);
+        }
+        println!(
+            "wrote {} ({} samples, buzzer on for {} frames)",
+            path,
+            wav_recorder.sample_count(),
+            wav_recorder.beep_frames()
+        );
+    }
+
+    if let (Some(path), Some(recorder)) = (options.record_path, recorder) {
+        let encoded = if path.ends_with(".png") || path.ends_with(".apng") {
+            recorder
+                .try_encode_apng(options.scale)
+                .map_err(|err| err.to_string())
+        } else {
+            recorder
+                .try_encode_gif(options.scale)
+                .map_err(|err| err.to_string())
+        };
+
+        match encoded.and_then(|bytes| fs::write(&path, bytes).map_err(|err| err.to_string())) {
+            Ok(()) => println!(
+                "wrote {} ({} frames, {} unique)",
+                path,
+                recorder.duration_frames(),
+                recorder.frame_count()
+            ),
+            Err(err) => {
+                eprintln!("failed to write {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+}
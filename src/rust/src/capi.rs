@@ -0,0 +1,299 @@
+// C API for embedding the core in C and C++ hosts. Machines are opaque
+// handles from `chip8_new`; every call reports failure through `Chip8Status`
+// instead of unwinding into the host, and data is copied out into buffers the
+// caller owns. `include/chip8.h` is generated from this file by cbindgen, so
+// the `///` comments here become the header's documentation.
+use std::{
+    os::raw::{c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{Chip8, FRAME_BUF_HEIGHT, FRAME_BUF_MAX, FRAME_BUF_WIDTH, KEY_COUNT};
+
+// cbindgen copies literal values only
+/// Pixels per row of the screen.
+pub const CHIP8_SCREEN_WIDTH: usize = 64;
+/// Rows of the screen.
+pub const CHIP8_SCREEN_HEIGHT: usize = 32;
+/// Bytes `chip8_screen` writes: one per pixel, row by row.
+pub const CHIP8_SCREEN_SIZE: usize = 2048;
+/// Keys 0x0-0xF.
+pub const CHIP8_KEY_COUNT: u8 = 16;
+
+const _: () = assert!(
+    CHIP8_SCREEN_WIDTH == FRAME_BUF_WIDTH
+        && CHIP8_SCREEN_HEIGHT == FRAME_BUF_HEIGHT
+        && CHIP8_SCREEN_SIZE == FRAME_BUF_MAX
+        && CHIP8_KEY_COUNT as usize == KEY_COUNT
+);
+
+/// Result of every call that can fail.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Status {
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// A key or ROM size was out of range.
+    InvalidArgument = 2,
+    /// The output buffer is too small. Calls that say so report the size
+    /// needed.
+    BufferTooSmall = 3,
+    /// The bytes are not a save state this version can read.
+    InvalidState = 4,
+    /// The program hit an error, such as an unknown opcode, and the machine
+    /// stopped. `chip8_load_state`, or `chip8_reset` followed by
+    /// `chip8_load_rom`, starts it again.
+    Halted = 5,
+    /// A bug in the emulator. The machine should be freed.
+    Panic = 6,
+}
+
+// Runs `body` on the machine behind `chip8`, turning a null handle and a
+// panic into status codes
+unsafe fn with_machine(
+    chip8: *mut Chip8,
+    body: impl FnOnce(&mut Chip8) -> Chip8Status,
+) -> Chip8Status {
+    match chip8.as_mut() {
+        Some(chip8) => {
+            panic::catch_unwind(AssertUnwindSafe(|| body(chip8))).unwrap_or(Chip8Status::Panic)
+        }
+        None => Chip8Status::NullPointer,
+    }
+}
+
+// `with_machine` for calls that only read the machine
+unsafe fn with_machine_ref(
+    chip8: *const Chip8,
+    body: impl FnOnce(&Chip8) -> Chip8Status,
+) -> Chip8Status {
+    match chip8.as_ref() {
+        Some(chip8) => {
+            panic::catch_unwind(AssertUnwindSafe(|| body(chip8))).unwrap_or(Chip8Status::Panic)
+        }
+        None => Chip8Status::NullPointer,
+    }
+}
+
+// A null pointer is only allowed for an empty buffer
+unsafe fn input<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn output<'a>(data: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        Some(&mut [])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts_mut(data, len))
+    }
+}
+
+/// Creates a machine whose random numbers come from `seed`. Returns null if
+/// it could not be created. Free it with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8 {
+    panic::catch_unwind(|| {
+        let mut chip8 = Chip8::with_seed(seed);
+        // C hosts have no other way to pick settings for a ROM
+        chip8.set_auto_detect(true);
+        Box::into_raw(Box::new(chip8))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Frees a machine from `chip8_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `chip8` must be null or a machine from `chip8_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Copies `len` bytes of ROM into memory at 0x200. Known ROMs get the quirks
+/// and speed recorded in the ROM database, and others the quirks of the
+/// platform they most likely target.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, and `rom` must point to `len` readable
+/// bytes unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    chip8: *mut Chip8,
+    rom: *const u8,
+    len: usize,
+) -> Chip8Status {
+    with_machine(chip8, |chip8| {
+        let rom = match input(rom, len) {
+            Some(rom) => rom,
+            None => return Chip8Status::NullPointer,
+        };
+        if rom.len() > chip8.max_rom_size() {
+            return Chip8Status::InvalidArgument;
+        }
+        chip8.load_rom(rom);
+        Chip8Status::Ok
+    })
+}
+
+/// Clears the screen, the stack, the timers and memory from 0x200, and
+/// unloads the ROM. V0-VF keep their values. Load a ROM again with
+/// `chip8_load_rom` before running the machine.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip8: *mut Chip8) -> Chip8Status {
+    with_machine(chip8, |chip8| {
+        chip8.reset();
+        Chip8Status::Ok
+    })
+}
+
+/// Runs one 60Hz frame. Returns `CHIP8_STATUS_HALTED` once the program has
+/// hit an error.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> Chip8Status {
+    with_machine(chip8, |chip8| {
+        chip8.run_frame();
+        if chip8.error.is_some() {
+            Chip8Status::Halted
+        } else {
+            Chip8Status::Ok
+        }
+    })
+}
+
+/// Presses or releases `key`, 0x0-0xF.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) -> Chip8Status {
+    with_machine(chip8, |chip8| {
+        if key >= CHIP8_KEY_COUNT {
+            return Chip8Status::InvalidArgument;
+        }
+        if pressed {
+            chip8.set_key(key);
+        } else {
+            chip8.unset_key(key);
+        }
+        Chip8Status::Ok
+    })
+}
+
+/// Copies the screen into `out`, `CHIP8_SCREEN_SIZE` bytes of 0 or 1 row by
+/// row.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, and `out` must point to `len`
+/// writable bytes unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screen(
+    chip8: *const Chip8,
+    out: *mut u8,
+    len: usize,
+) -> Chip8Status {
+    with_machine_ref(chip8, |chip8| {
+        let out = match output(out, len) {
+            Some(out) => out,
+            None => return Chip8Status::NullPointer,
+        };
+        if out.len() < CHIP8_SCREEN_SIZE {
+            return Chip8Status::BufferTooSmall;
+        }
+        out[..CHIP8_SCREEN_SIZE].copy_from_slice(&chip8.frame_buffer);
+        Chip8Status::Ok
+    })
+}
+
+/// Saves the machine's state into `out` for `chip8_load_state` and stores
+/// its size in `written`. If `len` is too small, nothing is copied and
+/// `CHIP8_STATUS_BUFFER_TOO_SMALL` is returned, so calling with a null `out`
+/// and a `len` of 0 asks for the size.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, `out` must point to `len` writable
+/// bytes unless `len` is 0, and `written` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    chip8: *const Chip8,
+    out: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> Chip8Status {
+    with_machine_ref(chip8, |chip8| {
+        let (out, written) = match (output(out, len), written.as_mut()) {
+            (Some(out), Some(written)) => (out, written),
+            _ => return Chip8Status::NullPointer,
+        };
+        let state = chip8.save_state();
+        *written = state.len();
+        if out.len() < state.len() {
+            return Chip8Status::BufferTooSmall;
+        }
+        out[..state.len()].copy_from_slice(&state);
+        Chip8Status::Ok
+    })
+}
+
+/// Restores a state from `chip8_save_state`. The machine is left alone if the
+/// bytes are not a state this version can read.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, and `state` must point to `len`
+/// readable bytes unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    chip8: *mut Chip8,
+    state: *const u8,
+    len: usize,
+) -> Chip8Status {
+    with_machine(chip8, |chip8| match input(state, len) {
+        Some(state) if chip8.load_state(state) => Chip8Status::Ok,
+        Some(_) => Chip8Status::InvalidState,
+        None => Chip8Status::NullPointer,
+    })
+}
+
+/// A short description of `status`, valid for the life of the program.
+/// Values that are not a `Chip8Status` get "unknown status".
+#[no_mangle]
+pub extern "C" fn chip8_status_message(status: c_int) -> *const c_char {
+    // Matched as an integer, since C can pass any value
+    let message: &'static [u8] = match status {
+        0 => b"ok\0",
+        1 => b"null pointer\0",
+        2 => b"argument out of range\0",
+        3 => b"buffer too small\0",
+        4 => b"not a save state this version can read\0",
+        5 => b"the program hit an error and stopped\0",
+        6 => b"internal error\0",
+        _ => b"unknown status\0",
+    };
+    message.as_ptr() as *const c_char
+}
@@ -0,0 +1,253 @@
+// Conformance runner for community CHIP-8 test ROMs. Each suite is run
+// headlessly under every quirks profile and its result screen is read back by
+// matching the ROM's pass/fail glyphs at known positions in the frame buffer.
+use crate::{Chip8, Pixel, Quirks, FRAME_BUF_HEIGHT, FRAME_BUF_WIDTH, QUIRK_PROFILES};
+
+const SEED: u64 = 0;
+
+// A bitmap drawn by a test ROM, one string per row using '#' for lit pixels
+pub struct Glyph {
+    pub name: &'static str,
+    pub rows: &'static [&'static str],
+}
+
+// Where a test ROM draws the result of one sub-test
+pub struct Cell {
+    pub label: &'static str,
+    pub x: usize,
+    pub y: usize,
+}
+
+pub struct Suite {
+    pub name: &'static str,
+    // File name inside `test_roms/`
+    pub rom: &'static str,
+    pub frames: u32,
+    pub pass: Glyph,
+    // None for ROMs that only draw a picture when everything works
+    pub fail: Option<Glyph>,
+    pub cells: &'static [Cell],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    // Neither glyph was found where the result should be
+    Unreadable,
+}
+
+#[derive(Debug)]
+pub struct SubTestResult {
+    pub label: &'static str,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug)]
+pub enum SuiteStatus {
+    Ran(Vec<SubTestResult>),
+    RomMissing,
+}
+
+#[derive(Debug)]
+pub struct SuiteReport {
+    pub suite: &'static str,
+    pub profile: &'static str,
+    pub status: SuiteStatus,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> bool {
+        match &self.status {
+            SuiteStatus::Ran(results) => results.iter().all(|r| r.outcome == Outcome::Pass),
+            _ => false,
+        }
+    }
+}
+
+impl Glyph {
+    pub fn width(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    // Exact match of the glyph with its top-left corner at (x, y). An empty
+    // glyph matches nowhere.
+    pub fn matches_at(&self, frame: &[Pixel], x: usize, y: usize) -> bool {
+        if self.width() == 0
+            || x + self.width() > FRAME_BUF_WIDTH
+            || y + self.height() > FRAME_BUF_HEIGHT
+        {
+            return false;
+        }
+        self.rows.iter().enumerate().all(|(row, pattern)| {
+            pattern.bytes().enumerate().all(|(col, expected)| {
+                let lit = frame[(y + row) * FRAME_BUF_WIDTH + x + col] != 0;
+                lit == (expected == b'#')
+            })
+        })
+    }
+
+    // Every position the glyph appears at, scanning row by row
+    pub fn find_all(&self, frame: &[Pixel]) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        for y in 0..FRAME_BUF_HEIGHT {
+            for x in 0..FRAME_BUF_WIDTH {
+                if self.matches_at(frame, x, y) {
+                    found.push((x, y));
+                }
+            }
+        }
+        found
+    }
+}
+
+const fn cell(label: &'static str, x: usize, y: usize) -> Cell {
+    Cell { label, x, y }
+}
+
+// corax89's opcode test: a 3x6 grid of opcode labels each followed by OK or NO
+const CORAX_CELLS: &[Cell] = &[
+    cell("3XNN", 10, 1),
+    cell("00EE", 32, 1),
+    cell("8XY5", 52, 1),
+    cell("4XNN", 10, 6),
+    cell("8XY0", 32, 6),
+    cell("8XY6", 52, 6),
+    cell("5XY0", 10, 11),
+    cell("8XY1", 32, 11),
+    cell("8XYE", 52, 11),
+    cell("7XNN", 10, 16),
+    cell("8XY2", 32, 16),
+    cell("FX55", 52, 16),
+    cell("9XY0", 10, 21),
+    cell("8XY3", 32, 21),
+    cell("FX33", 52, 21),
+    cell("ANNN", 10, 26),
+    cell("8XY4", 32, 26),
+    cell("FX1E", 52, 26),
+];
+
+// Timendus' IBM logo, with the signature drawn to its right. Every other row
+// of the letters is left blank.
+const IBM_LOGO: Glyph = Glyph {
+    name: "IBM logo",
+    rows: &[
+        "########.#########...#####.........#####..#.#.",
+        "..........................................#.#.",
+        "########.###########.######.......######...#..",
+        "..............................................",
+        "..####.....###...###...#####.....#####....#.#.",
+        "..........................................###.",
+        "..####.....#######.....#######.#######......#.",
+        "............................................#.",
+        "..####.....#######.....###.#######.###........",
+        "...........................................#..",
+        "..####.....###...###...###..#####..###........",
+        "..........................................###.",
+        "########.###########.#####...###...#####....#.",
+        "..........................................##..",
+        "########.#########...#####....#....#####..###.",
+    ],
+};
+
+// Timendus' suites that are not checked yet, as (name, ROM file name). Their
+// ROMs are not in `test_roms/`, and each result screen has to be mapped from
+// the ROM itself before it can move into `SUITES`. quirks and keypad also
+// need a platform or test picked through memory at 0x1FF, keypad needs keys
+// pressed, and beep has to be read from `is_beeping` rather than the screen.
+pub const PENDING_SUITES: &[(&str, &str)] = &[
+    ("corax+", "3-corax+.ch8"),
+    ("flags", "4-flags.ch8"),
+    ("quirks", "5-quirks.ch8"),
+    ("keypad", "6-keypad.ch8"),
+    ("beep", "7-beep.ch8"),
+];
+
+pub const SUITES: &[Suite] = &[
+    Suite {
+        name: "corax",
+        rom: "test_opcode.ch8",
+        frames: 120,
+        pass: Glyph {
+            name: "OK",
+            rows: &["###.#.#", "#.#.##.", "#.#.#.#", "###.#.#"],
+        },
+        fail: Some(Glyph {
+            name: "NO",
+            rows: &["##..###", "#.#.#.#", "#.#.#.#", "#.#.###"],
+        }),
+        cells: CORAX_CELLS,
+    },
+    Suite {
+        name: "ibm-logo",
+        rom: "2-ibm-logo.ch8",
+        frames: 60,
+        pass: IBM_LOGO,
+        fail: None,
+        cells: &[cell("logo", 12, 8)],
+    },
+];
+
+impl Suite {
+    pub fn run(&self, rom: &[u8], quirks: Quirks) -> SuiteStatus {
+        let mut chip8 = Chip8::with_seed(SEED);
+        chip8.set_quirks(quirks);
+        chip8.load_rom(rom);
+        for _ in 0..self.frames {
+            chip8.run_frame();
+        }
+
+        SuiteStatus::Ran(self.read_results(chip8.frame_buffer()))
+    }
+
+    pub fn read_results(&self, frame: &[Pixel]) -> Vec<SubTestResult> {
+        self.cells
+            .iter()
+            .map(|cell| {
+                let outcome = if self.pass.matches_at(frame, cell.x, cell.y) {
+                    Outcome::Pass
+                } else if self
+                    .fail
+                    .as_ref()
+                    .is_some_and(|fail| fail.matches_at(frame, cell.x, cell.y))
+                {
+                    Outcome::Fail
+                } else {
+                    Outcome::Unreadable
+                };
+                SubTestResult {
+                    label: cell.label,
+                    outcome,
+                }
+            })
+            .collect()
+    }
+}
+
+// Runs every suite under every quirks profile. `load_rom` is given a file
+// name from `test_roms/` and returns None when the ROM is not available.
+pub fn run_all<F>(load_rom: F) -> Vec<SuiteReport>
+where
+    F: Fn(&str) -> Option<Vec<u8>>,
+{
+    let mut reports = Vec::new();
+    for suite in SUITES {
+        let rom = load_rom(suite.rom);
+        for &(profile, quirks) in QUIRK_PROFILES {
+            let status = match &rom {
+                Some(rom) => suite.run(rom, quirks),
+                None => SuiteStatus::RomMissing,
+            };
+            reports.push(SuiteReport {
+                suite: suite.name,
+                profile,
+                status,
+            });
+        }
+    }
+    reports
+}
@@ -0,0 +1,82 @@
+// Predecoded instructions for the `CachedDecode` engine. The first time an
+// address runs, its opcode is fetched once and resolved to the handler
+// `handle_opcode` would dispatch it to; later visits call the handler
+// directly. Writes to memory invalidate the entries they overlap, so
+// self-modifying code still sees its own changes.
+use wasm_bindgen::prelude::*;
+
+use crate::{Chip8, Chip8Error, MEM_MAX};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionEngine {
+    // Fetch and dispatch every instruction
+    Interpreter,
+    // Reuse the decoded handler for addresses that already ran
+    CachedDecode,
+    // Run hot straight-line code as compiled blocks; see `jit`
+    BlockJit,
+}
+
+#[derive(Clone, Copy)]
+pub struct CachedOp {
+    pub handler: fn(&mut Chip8, u16),
+    pub opcode: u16,
+}
+
+pub fn decode(opcode: u16) -> CachedOp {
+    let handler: fn(&mut Chip8, u16) = match opcode & 0xF000 {
+        0x0000 => Chip8::sys_addr,
+        0x1000 => Chip8::jp_addr,
+        0x2000 => Chip8::call_addr,
+        0x3000 => Chip8::skip_if_equal,
+        0x4000 => Chip8::skip_if_not_equal,
+        0x5000 => Chip8::skip_if_reg_equal,
+        0x6000 => Chip8::set_reg,
+        0x7000 => Chip8::add_reg,
+        0x8000 => Chip8::reg_ops,
+        0x9000 => Chip8::skip_if_reg_not_equal,
+        0xA000 => Chip8::set_index,
+        0xB000 => Chip8::jp_offset,
+        0xC000 => Chip8::rand,
+        0xD000 => Chip8::display_sprite,
+        0xE000 => Chip8::skip_if_key_state,
+        0xF000 => Chip8::misc,
+        _ => |chip8, opcode| chip8.fault(Chip8Error::UnhandledOpcode(opcode)),
+    };
+    CachedOp { handler, opcode }
+}
+
+// One slot per address. Empty until the engine is first used, so machines
+// on the plain interpreter do not pay for it.
+#[derive(Default)]
+pub struct DecodeCache {
+    entries: Vec<Option<CachedOp>>,
+}
+
+impl DecodeCache {
+    pub fn get(&self, address: usize) -> Option<CachedOp> {
+        self.entries.get(address).copied().flatten()
+    }
+
+    pub fn insert(&mut self, address: usize, op: CachedOp) {
+        if self.entries.is_empty() {
+            self.entries = vec![None; MEM_MAX];
+        }
+        self.entries[address] = Some(op);
+    }
+
+    // Drops every entry whose opcode overlaps `start..end`, including one
+    // that starts on the byte before
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        let start = start.saturating_sub(1);
+        let end = end.min(self.entries.len());
+        if start < end {
+            self.entries[start..end].fill(None);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
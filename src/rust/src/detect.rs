@@ -0,0 +1,196 @@
+// Guesses which platform a ROM was written for by scanning its reachable code
+// for instructions that only later interpreters understand, and for patterns
+// whose meaning depends on the quirks profile.
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::ControlFlowGraph;
+use crate::{get_x, get_y, Quirks};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    // Entry in `QUIRK_PROFILES` that guesses for this platform start from.
+    // Plain CHIP-8 ROMs get the emulator's default rather than the strict
+    // COSMAC VIP profile, which most of them were not written against.
+    pub fn profile_name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "default",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::DEFAULT,
+            Platform::SuperChip => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlatformGuess {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // 0.0 (no idea) to 1.0 (the ROM uses instructions only this platform has)
+    pub confidence: f32,
+}
+
+// What the scan found; each flag is one kind of evidence
+#[derive(Default)]
+struct Evidence {
+    // F000 nnnn
+    long_load: bool,
+    // Fn01 plane, F002 audio, Fx3A pitch, 5xy2/5xy3, 00Dn
+    xo_only: bool,
+    // 00FE/00FF
+    hires_switch: bool,
+    // 00Cn, 00FB-00FD, Dxy0, Fx30, Fx75, Fx85
+    schip_only: bool,
+    // 8xy6/8xyE reading a register other than the one written
+    shift_from_vy: bool,
+    // Bxnn with x != 0, which only lands the same on both jump variants by luck
+    jump_with_vx: bool,
+    // Fx55/Fx65 followed by another use of I with no reload in between
+    relies_on_increment: bool,
+}
+
+impl Evidence {
+    fn scan(cfg: &ControlFlowGraph) -> Self {
+        let mut evidence = Evidence::default();
+        for block in cfg.blocks.values() {
+            // Set after Fx55/Fx65 until I is loaded again
+            let mut i_advanced = false;
+            for &(_, opcode) in &block.instructions {
+                let x = get_x(opcode);
+                let y = get_y(opcode);
+                let n = opcode & 0xF;
+                let kk = opcode & 0xFF;
+                let uses_i = match opcode & 0xF000 {
+                    0x0000 => {
+                        match opcode {
+                            0x00FE | 0x00FF => evidence.hires_switch = true,
+                            0x00FB..=0x00FD => evidence.schip_only = true,
+                            _ if opcode & 0xFFF0 == 0x00C0 => evidence.schip_only = true,
+                            _ if opcode & 0xFFF0 == 0x00D0 => evidence.xo_only = true,
+                            _ => {}
+                        }
+                        false
+                    }
+                    0x5000 if n == 2 || n == 3 => {
+                        evidence.xo_only = true;
+                        true
+                    }
+                    0x8000 if (n == 0x6 || n == 0xE) && x != y && y != 0 => {
+                        evidence.shift_from_vy = true;
+                        false
+                    }
+                    0xA000 => {
+                        i_advanced = false;
+                        false
+                    }
+                    0xB000 if x != 0 => {
+                        evidence.jump_with_vx = true;
+                        false
+                    }
+                    0xD000 => {
+                        if n == 0 {
+                            evidence.schip_only = true;
+                        }
+                        true
+                    }
+                    0xF000 if opcode == 0xF000 => {
+                        evidence.long_load = true;
+                        i_advanced = false;
+                        false
+                    }
+                    0xF000 => match kk {
+                        0x01 | 0x02 | 0x3A => {
+                            evidence.xo_only = true;
+                            kk == 0x02
+                        }
+                        0x30 | 0x75 | 0x85 => {
+                            evidence.schip_only = true;
+                            if kk == 0x30 {
+                                i_advanced = false;
+                            }
+                            false
+                        }
+                        0x29 => {
+                            i_advanced = false;
+                            false
+                        }
+                        0x1E | 0x33 => true,
+                        0x55 | 0x65 => {
+                            if i_advanced {
+                                evidence.relies_on_increment = true;
+                            }
+                            i_advanced = true;
+                            false
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if uses_i && i_advanced {
+                    evidence.relies_on_increment = true;
+                }
+            }
+        }
+        evidence
+    }
+}
+
+#[wasm_bindgen]
+pub fn detect_platform(rom: &[u8]) -> PlatformGuess {
+    let cfg = ControlFlowGraph::build(rom);
+    let evidence = Evidence::scan(&cfg);
+
+    let (platform, mut confidence) = if evidence.long_load || evidence.xo_only {
+        let confidence = if evidence.long_load && evidence.xo_only {
+            0.95
+        } else {
+            0.85
+        };
+        (Platform::XoChip, confidence)
+    } else if evidence.hires_switch || evidence.schip_only {
+        let confidence = if evidence.hires_switch { 0.9 } else { 0.8 };
+        (Platform::SuperChip, confidence)
+    } else {
+        // Only the absence of newer instructions points here
+        (Platform::Chip8, 0.6)
+    };
+    let mut quirks = platform.quirks();
+
+    // Patterns that pin down individual quirks. Each one that has to be
+    // guessed rather than read off the code costs some confidence.
+    if evidence.relies_on_increment {
+        quirks.memory_increment = true;
+    }
+    if evidence.shift_from_vy {
+        if platform == Platform::Chip8 {
+            quirks.shift_uses_vy = true;
+        }
+        confidence -= 0.1;
+    }
+    if evidence.jump_with_vx {
+        confidence -= 0.1;
+    }
+    if cfg.blocks.is_empty() {
+        confidence = 0.0;
+    }
+
+    PlatformGuess {
+        platform,
+        quirks,
+        confidence,
+    }
+}
@@ -0,0 +1,49 @@
+// Bounding box of the pixels that changed since a renderer last looked, so
+// it only has to convert and upload that part of the screen.
+use wasm_bindgen::prelude::*;
+
+use crate::{FRAME_BUF_HEIGHT, FRAME_BUF_WIDTH};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRegion {
+    pub const FULL_SCREEN: DirtyRegion = DirtyRegion {
+        x: 0,
+        y: 0,
+        width: FRAME_BUF_WIDTH,
+        height: FRAME_BUF_HEIGHT,
+    };
+
+    // The lit span of a packed row, bit 63 being the leftmost pixel
+    pub fn row(y: usize, bits: u64) -> Option<Self> {
+        if bits == 0 {
+            return None;
+        }
+        let x = bits.leading_zeros() as usize;
+        Some(DirtyRegion {
+            x,
+            y,
+            width: FRAME_BUF_WIDTH - bits.trailing_zeros() as usize - x,
+            height: 1,
+        })
+    }
+
+    // Smallest region covering both
+    pub fn union(&self, other: &DirtyRegion) -> DirtyRegion {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        DirtyRegion {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
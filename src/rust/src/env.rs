@@ -0,0 +1,515 @@
+// Gym-style environment for training agents on a ROM. `reset` starts an
+// episode from a seed and `step` holds one action for a few frames, returning
+// the screen, the reward earned and whether the episode is over.
+//
+// Actions are a no-op followed by each key the ROM tests, as found by
+// `ControlFlowGraph::keys_read`. Rewards come from a `RewardTable`, parsed at
+// run time from JSON laid out like `rewards.json`: for each known game it
+// lists where the score is kept, which values mean the game is over, and a
+// key to press to get past a title screen. `RewardTable::builtin` is the
+// `rewards.json` shipped with the crate, and `Env::with_rewards` takes any
+// other table. ROMs without an entry earn nothing and only end on a fault or
+// the step limit.
+use std::{fmt, fs, io, path::Path, sync::OnceLock};
+
+use rand::SeedableRng;
+use serde_json::Value;
+
+use crate::{
+    analysis::ControlFlowGraph, batch::apply_keys, rng::Xoshiro256PlusPlus, Chip8, Pixel,
+    KEY_COUNT, MEM_MAX, REG_MAX,
+};
+
+// Frames each action is held for unless `set_frame_skip` says otherwise
+pub const DEFAULT_FRAME_SKIP: u32 = 4;
+
+// Seed for the frames run before the first episode, so every `Env` for a ROM
+// starts its episodes from the same state
+const WARM_UP_SEED: u64 = 0;
+
+const BUILTIN_REWARDS: &str = include_str!("../rewards.json");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreSource {
+    Memory(u16),
+    // For games that keep their score in a register and never store it
+    Register(u8),
+}
+
+// Which changes of a score term count towards the reward. Counters that are
+// refilled or wrap around only count the way the score moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreDirection {
+    Both,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreTerm {
+    pub source: ScoreSource,
+    // Count the set bits instead of reading the byte as a number, for
+    // masks of what is still on screen
+    pub bits: bool,
+    // Reward per unit the value changes by
+    pub weight: f32,
+    pub direction: ScoreDirection,
+}
+
+#[derive(Clone, Debug)]
+pub struct RewardEntry {
+    pub sha1: [u8; 20],
+    pub title: String,
+    pub score: Vec<ScoreTerm>,
+    // The game is over once any of these holds its value
+    pub done_when: Vec<(ScoreSource, u8)>,
+    // Key held for `start_frames` frames before every episode
+    pub start_key: Option<u8>,
+    pub start_frames: u32,
+}
+
+// Reward entries sorted by SHA-1, like the ROM database
+#[derive(Clone, Debug, Default)]
+pub struct RewardTable {
+    entries: Vec<RewardEntry>,
+}
+
+#[derive(Debug)]
+pub enum RewardError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    // Valid JSON that does not describe rewards, and why
+    Invalid(String),
+}
+
+impl fmt::Display for RewardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewardError::Io(err) => write!(f, "failed to read rewards: {}", err),
+            RewardError::Json(err) => write!(f, "rewards are not valid JSON: {}", err),
+            RewardError::Invalid(reason) => write!(f, "bad reward entry: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RewardError {}
+
+impl From<io::Error> for RewardError {
+    fn from(err: io::Error) -> Self {
+        RewardError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for RewardError {
+    fn from(err: serde_json::Error) -> Self {
+        RewardError::Json(err)
+    }
+}
+
+// `Env::step` was given an action past the last one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidAction {
+    pub action: usize,
+    pub count: usize,
+}
+
+impl fmt::Display for InvalidAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "action {} is out of range for {} actions",
+            self.action, self.count
+        )
+    }
+}
+
+impl std::error::Error for InvalidAction {}
+
+impl RewardTable {
+    // `json` is an array of entries laid out like `rewards.json`
+    pub fn parse(json: &str) -> Result<Self, RewardError> {
+        let entries: Vec<Value> = serde_json::from_str(json)?;
+        let mut entries = entries
+            .iter()
+            .map(parse_entry)
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.sha1);
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].sha1 == pair[1].sha1) {
+            return Err(invalid(format!("duplicate sha1 for {}", pair[1].title)));
+        }
+        Ok(RewardTable { entries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RewardError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // The `rewards.json` shipped with the crate, parsed on first use
+    pub fn builtin() -> &'static RewardTable {
+        static BUILTIN: OnceLock<RewardTable> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            RewardTable::parse(BUILTIN_REWARDS).expect("the bundled rewards.json is invalid")
+        })
+    }
+
+    pub fn entries(&self) -> &[RewardEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, rom: &[u8]) -> Option<&RewardEntry> {
+        let sha1 = sha1_smol::Sha1::from(rom).digest().bytes();
+        self.entries
+            .binary_search_by(|entry| entry.sha1.cmp(&sha1))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+fn invalid(reason: String) -> RewardError {
+    RewardError::Invalid(reason)
+}
+
+fn field<'a>(object: &'a Value, name: &str) -> Result<&'a Value, RewardError> {
+    object
+        .get(name)
+        .ok_or_else(|| invalid(format!("missing \"{}\" in {}", name, object)))
+}
+
+fn number(object: &Value, name: &str, max: u64) -> Result<u64, RewardError> {
+    field(object, name)?
+        .as_u64()
+        .filter(|&value| value <= max)
+        .ok_or_else(|| invalid(format!("\"{}\" must be 0-{} in {}", name, max, object)))
+}
+
+fn parse_sha1(entry: &Value) -> Result<[u8; 20], RewardError> {
+    let text = field(entry, "sha1")?.as_str().unwrap_or_default();
+    let mut sha1 = [0; 20];
+    if text.len() != 40 || !text.is_ascii() {
+        return Err(invalid(format!("bad sha1 in {}", entry)));
+    }
+    for (byte, hex) in sha1.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = std::str::from_utf8(hex)
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| invalid(format!("bad sha1 in {}", entry)))?;
+    }
+    Ok(sha1)
+}
+
+// An object with a "memory" address like "0x2F3" or a "register" like "VE"
+fn parse_source(object: &Value) -> Result<ScoreSource, RewardError> {
+    let source = match (object.get("memory"), object.get("register")) {
+        (Some(address), None) => address
+            .as_str()
+            .and_then(|address| address.strip_prefix("0x"))
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            .filter(|&address| (address as usize) < MEM_MAX)
+            .map(ScoreSource::Memory),
+        (None, Some(register)) => register
+            .as_str()
+            .and_then(|register| register.strip_prefix('V'))
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+            .filter(|&index| (index as usize) < REG_MAX)
+            .map(ScoreSource::Register),
+        _ => None,
+    };
+    source.ok_or_else(|| {
+        invalid(format!(
+            "expected a \"memory\" address or a \"register\" in {}",
+            object
+        ))
+    })
+}
+
+fn parse_term(term: &Value) -> Result<ScoreTerm, RewardError> {
+    let bits = match term.get("read").map(Value::as_str) {
+        None | Some(Some("byte")) => false,
+        Some(Some("bits")) => true,
+        _ => {
+            return Err(invalid(format!(
+                "\"read\" must be \"byte\" or \"bits\" in {}",
+                term
+            )))
+        }
+    };
+    let weight = field(term, "weight")?
+        .as_f64()
+        .ok_or_else(|| invalid(format!("\"weight\" must be a number in {}", term)))?;
+    let direction = match term.get("direction").map(Value::as_str) {
+        None | Some(Some("both")) => ScoreDirection::Both,
+        Some(Some("up")) => ScoreDirection::Up,
+        Some(Some("down")) => ScoreDirection::Down,
+        _ => {
+            return Err(invalid(format!(
+                "\"direction\" must be \"both\", \"up\" or \"down\" in {}",
+                term
+            )))
+        }
+    };
+    Ok(ScoreTerm {
+        source: parse_source(term)?,
+        bits,
+        weight: weight as f32,
+        direction,
+    })
+}
+
+fn parse_entry(entry: &Value) -> Result<RewardEntry, RewardError> {
+    let array = |name| {
+        field(entry, name)?
+            .as_array()
+            .ok_or_else(|| invalid(format!("\"{}\" must be an array in {}", name, entry)))
+    };
+    let score = array("score")?
+        .iter()
+        .map(parse_term)
+        .collect::<Result<Vec<_>, _>>()?;
+    let done_when = match entry.get("done_when") {
+        None => Vec::new(),
+        Some(_) => array("done_when")?
+            .iter()
+            .map(|condition| {
+                let value = number(condition, "equals", u8::MAX as u64)?;
+                Ok((parse_source(condition)?, value as u8))
+            })
+            .collect::<Result<Vec<_>, RewardError>>()?,
+    };
+    let (start_key, start_frames) = match entry.get("start") {
+        None => (None, 0),
+        Some(start) => (
+            Some(number(start, "key", KEY_COUNT as u64 - 1)? as u8),
+            number(start, "frames", u32::MAX as u64)? as u32,
+        ),
+    };
+    Ok(RewardEntry {
+        sha1: parse_sha1(entry)?,
+        title: field(entry, "title")?
+            .as_str()
+            .ok_or_else(|| invalid(format!("\"title\" must be a string in {}", entry)))?
+            .to_string(),
+        score,
+        done_when,
+        start_key,
+        start_frames,
+    })
+}
+
+impl ScoreSource {
+    fn read(self, chip8: &Chip8) -> u8 {
+        match self {
+            ScoreSource::Memory(address) => chip8.memory[address as usize],
+            ScoreSource::Register(index) => chip8.reg[index as usize],
+        }
+    }
+}
+
+impl ScoreTerm {
+    fn value(&self, chip8: &Chip8) -> i32 {
+        let byte = self.source.read(chip8);
+        if self.bits {
+            byte.count_ones() as i32
+        } else {
+            byte as i32
+        }
+    }
+
+    fn reward(&self, before: i32, after: i32) -> f32 {
+        let change = after - before;
+        let change = match self.direction {
+            ScoreDirection::Both => change,
+            ScoreDirection::Up => change.max(0),
+            ScoreDirection::Down => change.min(0),
+        };
+        change as f32 * self.weight
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    // One byte per pixel, as in `Chip8::frame_buffer`
+    pub screen: Vec<Pixel>,
+}
+
+pub struct Env {
+    chip8: Chip8,
+    // Where every episode starts: right after loading, or after the start key
+    initial_state: Vec<u8>,
+    // Key held by each action; action 0 holds none
+    actions: Vec<Option<u8>>,
+    rewards: Option<RewardEntry>,
+    // Latest value of each score term
+    scores: Vec<i32>,
+    frame_skip: u32,
+    // None for episodes that only end with the game
+    max_steps: Option<u32>,
+    steps: u32,
+}
+
+impl Env {
+    // Rewards from `RewardTable::builtin`
+    pub fn new(rom: &[u8]) -> Self {
+        Self::with_rewards(rom, RewardTable::builtin())
+    }
+
+    pub fn with_rewards(rom: &[u8], table: &RewardTable) -> Self {
+        let mut chip8 = Chip8::with_seed(WARM_UP_SEED);
+        chip8.load_rom(rom);
+        let rewards = table.find(rom).cloned();
+        if let Some(RewardEntry {
+            start_key: Some(key),
+            start_frames,
+            ..
+        }) = rewards
+        {
+            apply_keys(&mut chip8, 1 << key);
+            for _ in 0..start_frames {
+                chip8.run_frame();
+            }
+            apply_keys(&mut chip8, 0);
+        }
+
+        let keys = ControlFlowGraph::build(rom).keys_read();
+        let actions = std::iter::once(None)
+            .chain(
+                (0..KEY_COUNT as u8)
+                    .filter(|key| keys >> key & 1 != 0)
+                    .map(Some),
+            )
+            .collect();
+        let mut env = Env {
+            initial_state: chip8.save_state(),
+            chip8,
+            actions,
+            rewards,
+            scores: Vec::new(),
+            frame_skip: DEFAULT_FRAME_SKIP,
+            max_steps: None,
+            steps: 0,
+        };
+        env.scores = env.read_scores();
+        env
+    }
+
+    // Starts a new episode with the random number generator seeded from `seed`
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.chip8.load_state(&self.initial_state);
+        self.chip8.rand_rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        self.scores = self.read_scores();
+        self.steps = 0;
+        self.observation()
+    }
+
+    // Holds the key for `action` for `frame_skip` frames, or until the game
+    // ends, and returns the screen, the reward summed over those frames and
+    // whether the episode is over. Nothing runs if `action` is out of range.
+    pub fn step(&mut self, action: usize) -> Result<(Observation, f32, bool), InvalidAction> {
+        let keys = match self.actions.get(action) {
+            Some(key) => key.map_or(0, |key| 1 << key),
+            None => {
+                return Err(InvalidAction {
+                    action,
+                    count: self.actions.len(),
+                })
+            }
+        };
+        apply_keys(&mut self.chip8, keys);
+
+        let mut reward = 0.0;
+        let mut done = false;
+        for _ in 0..self.frame_skip {
+            self.chip8.run_frame();
+            reward += self.collect_reward();
+            done = self.is_game_over();
+            if done {
+                break;
+            }
+        }
+        self.steps += 1;
+        done |= self.max_steps.is_some_and(|max| self.steps >= max);
+        Ok((self.observation(), reward, done))
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.actions.len()
+    }
+
+    // The key each action holds, None for the no-op
+    pub fn action_keys(&self) -> &[Option<u8>] {
+        &self.actions
+    }
+
+    // The reward entry for the ROM, if it has one
+    pub fn rewards(&self) -> Option<&RewardEntry> {
+        self.rewards.as_ref()
+    }
+
+    pub fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    // At least one frame
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.frame_skip = frames.max(1);
+    }
+
+    pub fn max_steps(&self) -> Option<u32> {
+        self.max_steps
+    }
+
+    pub fn set_max_steps(&mut self, steps: Option<u32>) {
+        self.max_steps = steps;
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    // For settings such as the execution engine. Episodes restart from a
+    // snapshot, which leaves settings alone.
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    fn observation(&self) -> Observation {
+        Observation {
+            screen: self.chip8.frame_buffer.to_vec(),
+        }
+    }
+
+    fn read_scores(&self) -> Vec<i32> {
+        self.rewards.as_ref().map_or_else(Vec::new, |rewards| {
+            rewards
+                .score
+                .iter()
+                .map(|term| term.value(&self.chip8))
+                .collect()
+        })
+    }
+
+    fn collect_reward(&mut self) -> f32 {
+        let scores = self.read_scores();
+        let reward = match &self.rewards {
+            Some(rewards) => rewards
+                .score
+                .iter()
+                .zip(self.scores.iter().zip(&scores))
+                .map(|(term, (&before, &after))| term.reward(before, after))
+                .sum(),
+            None => 0.0,
+        };
+        self.scores = scores;
+        reward
+    }
+
+    fn is_game_over(&self) -> bool {
+        self.chip8.error.is_some()
+            || self.rewards.as_ref().is_some_and(|rewards| {
+                rewards
+                    .done_when
+                    .iter()
+                    .any(|&(source, value)| source.read(&self.chip8) == value)
+            })
+    }
+}
@@ -0,0 +1,39 @@
+// Faults that stop the interpreter. The machine records the first one and
+// halts until it is reset, instead of panicking inside the host.
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnhandledOpcode(u16),
+    // 00EE with nothing to return to
+    StackUnderflow,
+    // 2nnn nested deeper than the stack allows
+    StackOverflow,
+    // An instruction read or wrote past the end of memory
+    MemoryOutOfBounds { address: usize },
+    // A ROM wrote to memory under write protection
+    WriteProtected { address: usize },
+    // Execution ran off the end of memory
+    ProgramCounterOutOfBounds { address: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnhandledOpcode(opcode) => write!(f, "Unhandled opcode 0x{:04x}", opcode),
+            Chip8Error::StackUnderflow => write!(f, "Return with an empty call stack"),
+            Chip8Error::StackOverflow => write!(f, "Call stack overflow"),
+            Chip8Error::MemoryOutOfBounds { address } => {
+                write!(f, "Memory access out of bounds at 0x{:04x}", address)
+            }
+            Chip8Error::WriteProtected { address } => {
+                write!(f, "Write to protected memory at 0x{:04x}", address)
+            }
+            Chip8Error::ProgramCounterOutOfBounds { address } => {
+                write!(f, "Program counter out of bounds at 0x{:04x}", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
@@ -0,0 +1,103 @@
+// Anti-flicker post-processing. Games erase and redraw their sprites with XOR
+// every frame, so a sprite caught mid-redraw at vblank vanishes for a frame.
+// The filter samples the screen once per frame and keeps a brightness per
+// pixel that the renderer shows instead of the raw frame buffer.
+use wasm_bindgen::prelude::*;
+
+use crate::{Pixel, FRAME_BUF_MAX};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlickerFilter {
+    Off,
+    // Lit pixels glow at full brightness and fade by the decay factor for
+    // every frame they stay off, like a CRT's phosphor
+    Phosphor,
+    // A pixel is lit if it was lit in this frame or the one before
+    MergeLastTwo,
+}
+
+pub const DEFAULT_PHOSPHOR_DECAY: f32 = 0.6;
+
+pub struct Phosphor {
+    pub mode: FlickerFilter,
+    // Brightness kept per frame, in 1/256ths
+    decay: u16,
+    // 0 (off) to 255 (fully lit)
+    intensity: [u8; FRAME_BUF_MAX],
+    previous: [Pixel; FRAME_BUF_MAX],
+}
+
+impl Phosphor {
+    pub fn new() -> Self {
+        let mut phosphor = Phosphor {
+            mode: FlickerFilter::Off,
+            decay: 0,
+            intensity: [0; FRAME_BUF_MAX],
+            previous: [0; FRAME_BUF_MAX],
+        };
+        phosphor.set_decay(DEFAULT_PHOSPHOR_DECAY);
+        phosphor
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay as f32 / 256.0
+    }
+
+    // Fraction of its brightness an unlit pixel keeps each frame, 0.0 to 1.0.
+    // Higher values leave longer trails; a pixel goes dark after about
+    // log(1/255) / log(decay) frames.
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = (decay.clamp(0.0, 1.0) * 256.0).round().min(255.0) as u16;
+    }
+
+    pub fn intensity(&self) -> &[u8] {
+        &self.intensity
+    }
+
+    // Starts over from `frame`, with no trails
+    pub fn restart(&mut self, frame: &[Pixel]) {
+        for (intensity, &pixel) in self.intensity.iter_mut().zip(frame) {
+            *intensity = if pixel != 0 { 0xFF } else { 0 };
+        }
+        self.previous.copy_from_slice(frame);
+    }
+
+    // Samples the screen at the end of a frame. Returns whether any
+    // brightness changed.
+    pub fn end_frame(&mut self, frame: &[Pixel]) -> bool {
+        let mut changed = false;
+        for ((intensity, previous), &pixel) in self
+            .intensity
+            .iter_mut()
+            .zip(self.previous.iter_mut())
+            .zip(frame)
+        {
+            let next = match self.mode {
+                FlickerFilter::Off => continue,
+                FlickerFilter::Phosphor if pixel != 0 => 0xFF,
+                FlickerFilter::Phosphor => (*intensity as u16 * self.decay / 256) as u8,
+                FlickerFilter::MergeLastTwo if pixel != 0 || *previous != 0 => 0xFF,
+                FlickerFilter::MergeLastTwo => 0,
+            };
+            changed |= next != *intensity;
+            *intensity = next;
+            *previous = pixel;
+        }
+        changed
+    }
+}
+
+// Writes opaque RGBA8 pixels blending the first two colors of the palette by
+// each pixel's brightness
+pub fn render_rgba(intensity: &[u8], colors: &[u32], rgba: &mut [u8]) {
+    let (background, foreground) = (colors[0], colors[1]);
+    for (&level, out) in intensity.iter().zip(rgba.chunks_exact_mut(4)) {
+        for (channel, shift) in out.iter_mut().zip([16, 8, 0]) {
+            let from = (background >> shift) as u8 as u32;
+            let to = (foreground >> shift) as u8 as u32;
+            *channel = ((from * (255 - level as u32) + to * level as u32) / 255) as u8;
+        }
+        out[3] = 0xFF;
+    }
+}
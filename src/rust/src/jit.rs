@@ -0,0 +1,211 @@
+// Block compiler for the `BlockJit` engine. Once a start address has run
+// often enough, the straight-line code from there is compiled into a list of
+// closures with their operands baked in. Unconditional jumps are followed, so
+// the block ends at the first other branch. A block then runs as one call
+// instead of one fetch and dispatch per instruction.
+//
+// Any other branch, and anything that can stall, fault or write memory, such
+// as Dxyn, Fx0A and Fx33/55/65, ends the block and runs as its exit through
+// the same handler the interpreter uses. Bnnn, whose target is only known at
+// run time, is left to the interpreter. Writes to memory that a block was
+// compiled from throw away every block, so self-modifying code recompiles
+// from its new bytes.
+//
+// Natively, blocks are Rust closures. The wasm build also translates runs of
+// register-only instructions into WebAssembly; see `wasm_block`.
+use crate::decode::{self, CachedOp};
+use crate::{get_kk, get_nnn, get_x, Chip8, MEM_MAX};
+
+// Visits to an address before it is compiled
+const HOT_THRESHOLD: u8 = 2;
+const MAX_BLOCK_LEN: usize = 64;
+
+pub type Op = Box<dyn Fn(&mut Chip8) + Send + Sync>;
+
+pub struct Block {
+    // Straight-line instructions, none of which touch the program counter,
+    // stall, fault or write memory
+    ops: Vec<Op>,
+    // The instruction that ends the block, if it ends on one
+    exit: Option<CachedOp>,
+    // Where the straight-line part leaves off, which is the exit if any
+    end: usize,
+    // Instructions in the block, counting followed jumps and the exit
+    count: usize,
+}
+
+impl Block {
+    // Instructions executed by `run` and the exit together
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    // Runs the straight-line part and leaves the program counter on the
+    // exit, which is returned for the caller to run
+    pub fn run(&self, chip8: &mut Chip8) -> Option<CachedOp> {
+        for op in &self.ops {
+            op(chip8);
+        }
+        chip8.program_counter = self.end;
+        self.exit
+    }
+}
+
+// Blocks by start address, one slot per address like `DecodeCache`. Lent out
+// of the cache while they run, so the machine can be borrowed alongside.
+#[derive(Default)]
+pub struct Blocks {
+    slots: Vec<Option<Box<Block>>>,
+    // The cache generation these were compiled in
+    generation: u64,
+}
+
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: Blocks,
+    // Bumped whenever every block has to go. Lent blocks from an older
+    // generation are dropped on their next lookup.
+    generation: u64,
+    // Visits to each address that has no block yet
+    hits: Vec<u8>,
+    // Bytes some block was compiled from
+    compiled: Vec<bool>,
+}
+
+impl BlockCache {
+    pub fn lend(&mut self) -> Blocks {
+        std::mem::take(&mut self.blocks)
+    }
+
+    pub fn restore(&mut self, blocks: Blocks) {
+        self.blocks = blocks;
+    }
+
+    // The block starting at `address`, compiling it from `memory` once the
+    // address is hot. An empty block means nothing there can be compiled.
+    pub fn lookup<'a>(
+        &mut self,
+        blocks: &'a mut Blocks,
+        address: usize,
+        memory: &[u8],
+    ) -> Option<&'a Block> {
+        if address + 1 >= MEM_MAX {
+            return None;
+        }
+        if self.hits.is_empty() {
+            self.hits = vec![0; MEM_MAX];
+            self.compiled = vec![false; MEM_MAX];
+        }
+        if blocks.slots.is_empty() {
+            blocks.slots = (0..MEM_MAX).map(|_| None).collect();
+        } else if blocks.generation != self.generation {
+            blocks.slots.iter_mut().for_each(|slot| *slot = None);
+        }
+        blocks.generation = self.generation;
+
+        let slot = &mut blocks.slots[address];
+        if slot.is_none() {
+            self.hits[address] = self.hits[address].saturating_add(1);
+            if self.hits[address] < HOT_THRESHOLD {
+                return None;
+            }
+            *slot = Some(Box::new(compile(address, memory, &mut self.compiled)));
+        }
+        slot.as_deref()
+    }
+
+    // Drops every block if any byte in `start..end` was compiled
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        let end = end.min(self.compiled.len());
+        if start < end && self.compiled[start..end].contains(&true) {
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.hits.fill(0);
+        self.compiled.fill(false);
+    }
+}
+
+enum Kind {
+    // Runs inside a block
+    Straight(Op),
+    // Continues the block at another address
+    Jump(usize),
+    // Ends a block and is part of it
+    Exit,
+    // Ends a block and is left to the interpreter
+    Unsupported,
+}
+
+fn classify(opcode: u16) -> Kind {
+    let x = get_x(opcode);
+    let kk = get_kk(opcode);
+    let generic = || -> Op {
+        let op = decode::decode(opcode);
+        Box::new(move |chip8| (op.handler)(chip8, op.opcode))
+    };
+    match opcode & 0xF000 {
+        0x6000 => Kind::Straight(Box::new(move |chip8| chip8.reg[x] = kk)),
+        0x7000 => Kind::Straight(Box::new(move |chip8| {
+            chip8.reg[x] = chip8.reg[x].wrapping_add(kk)
+        })),
+        0xA000 => {
+            let nnn = get_nnn(opcode);
+            Kind::Straight(Box::new(move |chip8| chip8.index_reg = nnn))
+        }
+        0x8000 if matches!(opcode & 0xF, 0x0..=0x7 | 0xE) => Kind::Straight(generic()),
+        0xC000 => Kind::Straight(generic()),
+        0xF000 if matches!(kk, 0x07 | 0x15 | 0x18 | 0x1E | 0x29) => Kind::Straight(generic()),
+        0x1000 => Kind::Jump(get_nnn(opcode) as usize),
+        0xB000 => Kind::Unsupported,
+        _ => Kind::Exit,
+    }
+}
+
+// Marks the bytes of every instruction it takes in `compiled`
+fn compile(start: usize, memory: &[u8], compiled: &mut [bool]) -> Block {
+    let mut block = Block {
+        ops: Vec::new(),
+        exit: None,
+        end: start,
+        count: 0,
+    };
+    // Straight-line instructions with their opcodes, for `link`
+    let mut straight = Vec::new();
+    let mut address = start;
+    while block.count < MAX_BLOCK_LEN && address + 1 < MEM_MAX {
+        let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+        let kind = classify(opcode);
+        if let Kind::Unsupported = kind {
+            break;
+        }
+        compiled[address..address + 2].fill(true);
+        block.count += 1;
+        match kind {
+            Kind::Straight(op) => {
+                straight.push((opcode, op));
+                address += 2;
+            }
+            Kind::Jump(target) => address = target,
+            Kind::Exit => {
+                block.exit = Some(decode::decode(opcode));
+                break;
+            }
+            Kind::Unsupported => unreachable!(),
+        }
+        block.end = address;
+    }
+    block.ops = link(straight);
+    block
+}
+
+#[cfg(target_arch = "wasm32")]
+use crate::wasm_block::link;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn link(straight: Vec<(u16, Op)>) -> Vec<Op> {
+    straight.into_iter().map(|(_, op)| op).collect()
+}
@@ -1,13 +1,21 @@
 use core::panic;
-use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rand::{Rng, SeedableRng};
 
 // https://www.cs.columbia.edu/~sedwards/classes/2016/4840-spring/designs/Chip8.pdf
 use wasm_bindgen::prelude::*;
 use web_sys::console;
 
+// Console logging only exists in the browser; native builds stay silent
+#[cfg(target_arch = "wasm32")]
 macro_rules! console_log {
     ($($t:tt)*) => (web_sys::console::log_1(&format_args!($($t)*).to_string().into()))
 }
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! console_log {
+    ($($t:tt)*) => {{
+        let _ = format_args!($($t)*);
+    }};
+}
 
 const FRAME_BUF_WIDTH: usize = 64;
 const FRAME_BUF_HEIGHT: usize = 32;
@@ -18,14 +26,78 @@ const START_OF_FONT: usize = 0x50;
 const MAX_ROM_SIZE: usize = MEM_MAX - START_OF_ROM;
 
 const REG_MAX: usize = 16;
+const KEY_COUNT: usize = 16;
 const FRAME_BUF_MAX: usize = FRAME_BUF_HEIGHT * FRAME_BUF_WIDTH;
 
+// Call depth allowed by default; the VIP managed 12, SUPER-CHIP 16
+const DEFAULT_STACK_LIMIT: usize = 16;
+// Where the stack lives when it is kept in emulated RAM, as on the VIP. Each
+// entry is a big-endian return address, growing upwards.
+const MEMORY_STACK_START: usize = 0xEA0;
+const MEMORY_STACK_END: usize = 0xED0;
+const MEMORY_STACK_ENTRIES: usize = (MEMORY_STACK_END - MEMORY_STACK_START) / 2;
+
+// Timers and the display run at 60Hz
+const FRAMES_PER_SECOND: u32 = 60;
+
+// Auto-detected quirks are only applied when the guess is at least this sure
+const AUTO_DETECT_MIN_CONFIDENCE: f32 = 0.5;
+
 type Pixel = u8;
+// One 64-pixel row. SUPER-CHIP's 128x64 mode would need u128 rows; that is
+// deferred until the interpreter implements 00FF and the other hires opcodes.
+type DisplayRow = u64;
+// `stale_rows` with every row set
+const ALL_ROWS: u32 = u32::MAX;
+
+pub mod analysis;
+mod audio;
+mod batch;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capi;
+pub mod conformance;
+mod decode;
+mod detect;
+mod dirty;
+pub mod env;
+mod error;
+mod filter;
+mod jit;
+mod memory_map;
+mod metadata;
+mod palette;
+mod quirks;
+mod record;
+mod rng;
+mod snapshot;
+mod timing;
+mod upscale;
+#[cfg(any(target_arch = "wasm32", test))]
+mod wasm_block;
+pub use audio::WavRecorder;
+pub use batch::Chip8Batch;
+pub use decode::ExecutionEngine;
+pub use detect::{detect_platform, Platform, PlatformGuess};
+pub use dirty::DirtyRegion;
+pub use error::Chip8Error;
+pub use filter::FlickerFilter;
+pub use memory_map::{MemoryRegion, RegionKind};
+pub use metadata::{lookup_rom, RomEntry, RomInfo};
+pub use palette::BuiltinPalette;
+pub use quirks::{Quirks, QUIRK_PROFILES};
+pub use record::{FrameSizeError, Recorder};
+pub use timing::TimingMode;
+pub use upscale::Upscaler;
+
+use decode::DecodeCache;
+use filter::Phosphor;
+use jit::BlockCache;
+use memory_map::{DISPLAY_END, DISPLAY_START, VIP_RESERVED_START};
+use palette::RGBA_SCREEN_LEN;
+use rng::Xoshiro256PlusPlus;
+use snapshot::Snapshot;
+use timing::{UNITS_PER_FRAME, UNITS_PER_MICRO};
 
-fn unhandled_opcode_panic(opcode: u16) {
-    console_log!("ERROR: Unhandled opcode 0x{:04x}", opcode);
-    panic!("Unhandled opcode 0x{:04x}", opcode);
-}
 // Opcode helper functions
 fn get_x(opcode: u16) -> usize {
     ((opcode & 0x0F00) >> 8) as usize
@@ -59,34 +131,106 @@ fn play_beep() {}
 
 #[wasm_bindgen]
 pub struct Chip8 {
-    // 0-512 bytes: Chip8 interpreter
-    // 0xF00-0xFFF: Display refresh
-    // 0xEA0-0xEFF: Call stack, internal use, and other variables
+    // See `memory_map` for the layout
     memory: [u8; MEM_MAX],
+    // The display is mirrored at 0xF00 and ROMs must end before 0xEA0
+    vip_layout: bool,
+    // Writes below 0x200 fault
+    write_protect: bool,
+    engine: ExecutionEngine,
+    // Handlers for addresses that already ran under `CachedDecode`
+    decode_cache: DecodeCache,
+    // Compiled blocks for `BlockJit`
+    block_cache: BlockCache,
+    // Bytes the program wrote since `Chip8Batch` last copied them out,
+    // empty when the start is past the end
+    memory_written: (usize, usize),
 
     // V0, V1...VF
     // Address register(12 bits wids)?
     reg: [u8; REG_MAX],
     index_reg: u16,
 
+    // Return addresses, unless the stack lives in `memory`
     stack: Vec<usize>,
+    stack_in_memory: bool,
+    // Entries in the in-memory stack
+    stack_pointer: usize,
+    // None for unbounded
+    stack_limit: Option<usize>,
 
-    // 64x32 frame buffer
+    // The screen, one bit per pixel with bit 63 leftmost, so a sprite row is
+    // drawn with a single XOR
+    display_rows: [DisplayRow; FRAME_BUF_HEIGHT],
+    // The same screen unpacked to one byte per pixel for `get_screen`. Draws
+    // only mark their rows in `stale_rows`; those are unpacked once when
+    // control returns to the host or a frame is presented.
     frame_buffer: [Pixel; FRAME_BUF_MAX],
+    // Bit y set when row y of `frame_buffer` is behind `display_rows`
+    stale_rows: u32,
+    // Pixels changed since the last `take_dirty_region`
+    dirty_region: Option<DirtyRegion>,
+    // The screen changed during the current frame; `update_canvas` is called
+    // once when it ends instead of after every draw
+    frame_changed: bool,
+    // Colors for each pixel value and the frame buffer rendered with them,
+    // redrawn on demand when either changed
+    palette: Vec<u32>,
+    rgba_screen: [u8; RGBA_SCREEN_LEN],
+    rgba_stale: bool,
+    // Anti-flicker filter applied to the RGBA screen
+    phosphor: Phosphor,
+    // The RGBA screen enlarged by `upscaler`, redrawn on demand like it
+    upscaler: Upscaler,
+    upscale_factor: usize,
+    upscaled_screen: Vec<u8>,
+    upscaled_stale: bool,
 
     program_counter: usize,
 
-    rand_rng: SmallRng,
+    rand_rng: Xoshiro256PlusPlus,
 
     keys: u16, // bitmask
 
     delay_timer: u8,
     sound_timer: u8,
+    // Whether the buzzer sounded during the most recent frame
+    is_beeping: bool,
 
     is_waiting_for_key: bool,
     reg_index_key_waiting: usize,
+    // A sprite was drawn under the display wait quirk; nothing runs until
+    // the current frame ends
+    is_waiting_for_vblank: bool,
 
     is_rom_loaded: bool,
+
+    quirks: Quirks,
+    timing_mode: TimingMode,
+    instructions_per_second: u32,
+    // Fixed mode: instructions owed to the next frame when the speed is not
+    // a multiple of 60, in 1/60ths of an instruction
+    instruction_carry: u32,
+    // Emulated time handed to `run_for` but not used yet, and how far into
+    // the current frame the machine is, both in `timing` units
+    time_budget: u64,
+    frame_position: u64,
+    // Whether `load_rom` configures the machine for the ROM, from the ROM
+    // database if it is listed there and from `detect_platform` otherwise
+    auto_detect: bool,
+    // Database entry for the loaded ROM, if it has one
+    rom_info: Option<RomInfo>,
+    // Result of the detection run by the last `load_rom`
+    platform_guess: Option<PlatformGuess>,
+
+    // First fault hit since the last reset; execution stops while set
+    error: Option<Chip8Error>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -94,27 +238,62 @@ impl Chip8 {
     pub fn new() -> Self {
         let mut chip8 = Self {
             memory: [0u8; MEM_MAX],
+            vip_layout: false,
+            write_protect: false,
+            engine: ExecutionEngine::Interpreter,
+            decode_cache: DecodeCache::default(),
+            block_cache: BlockCache::default(),
+            memory_written: (MEM_MAX, 0),
 
             reg: [0u8; REG_MAX],
             index_reg: 0,
 
             stack: Vec::new(),
+            stack_in_memory: false,
+            stack_pointer: 0,
+            stack_limit: Some(DEFAULT_STACK_LIMIT),
 
+            display_rows: [0; FRAME_BUF_HEIGHT],
             frame_buffer: [0; FRAME_BUF_MAX],
+            stale_rows: 0,
+            dirty_region: None,
+            frame_changed: false,
+            palette: BuiltinPalette::Monochrome.colors().to_vec(),
+            rgba_screen: [0; RGBA_SCREEN_LEN],
+            rgba_stale: true,
+            phosphor: Phosphor::new(),
+            upscaler: Upscaler::Nearest,
+            upscale_factor: 1,
+            upscaled_screen: Vec::new(),
+            upscaled_stale: true,
 
             program_counter: START_OF_ROM,
 
-            rand_rng: SmallRng::from_entropy(),
+            rand_rng: Xoshiro256PlusPlus::from_entropy(),
 
             keys: 0,
 
             delay_timer: 0,
             sound_timer: 0,
+            is_beeping: false,
 
             is_waiting_for_key: false,
             reg_index_key_waiting: 0,
+            is_waiting_for_vblank: false,
 
             is_rom_loaded: false,
+
+            quirks: Quirks::default(),
+            timing_mode: TimingMode::Fixed,
+            instructions_per_second: timing::DEFAULT_INSTRUCTIONS_PER_SECOND,
+            instruction_carry: 0,
+            time_budget: 0,
+            frame_position: 0,
+            auto_detect: false,
+            rom_info: None,
+            platform_guess: None,
+
+            error: None,
         };
 
         // Load font data into memory starting at 0x50
@@ -137,25 +316,295 @@ impl Chip8 {
     }
 
     pub fn load_rom(&mut self, rom: &[u8]) {
-        if rom.len() <= MAX_ROM_SIZE {
+        if rom.len() <= self.max_rom_size() {
             let rom_end = START_OF_ROM + rom.len();
             self.memory[START_OF_ROM..rom_end].copy_from_slice(rom);
+            self.clear_code_caches();
             self.is_rom_loaded = true;
+
+            self.rom_info = lookup_rom(rom);
+            if self.auto_detect {
+                self.configure_for_rom(rom);
+            }
+        }
+    }
+
+    // Largest ROM `load_rom` takes, which the VIP layout lowers
+    pub fn max_rom_size(&self) -> usize {
+        if self.vip_layout {
+            VIP_RESERVED_START - START_OF_ROM
+        } else {
+            MAX_ROM_SIZE
+        }
+    }
+
+    // Listed ROMs get their recorded settings; anything else gets a guess
+    fn configure_for_rom(&mut self, rom: &[u8]) {
+        if let Some(info) = self.rom_info {
+            let entry = info.entry();
+            self.quirks = entry.quirks;
+            self.set_ticks_per_frame(entry.ticks_per_frame);
+            self.set_custom_palette(&entry.palette);
+            self.platform_guess = Some(PlatformGuess {
+                platform: entry.platform,
+                quirks: entry.quirks,
+                confidence: 1.0,
+            });
+            return;
+        }
+
+        let guess = detect_platform(rom);
+        if guess.confidence >= AUTO_DETECT_MIN_CONFIDENCE {
+            self.quirks = guess.quirks;
         }
+        self.platform_guess = Some(guess);
     }
 
     pub fn reset(&mut self) {
         self.memory[0x200..MEM_MAX].fill(0);
+        self.clear_code_caches();
         self.stack.clear();
+        self.stack_pointer = 0;
+        self.display_rows.fill(0);
         self.frame_buffer.fill(0);
+        self.stale_rows = 0;
+        self.phosphor.restart(&self.frame_buffer);
+        self.mark_dirty(DirtyRegion::FULL_SCREEN);
         self.index_reg = 0;
         self.program_counter = 0x200;
         self.keys = 0;
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.is_beeping = false;
         self.is_waiting_for_key = false;
         self.reg_index_key_waiting = 0;
+        self.is_waiting_for_vblank = false;
+        self.instruction_carry = 0;
+        self.time_budget = 0;
+        self.frame_position = 0;
         self.is_rom_loaded = false;
+        self.rom_info = None;
+        self.platform_guess = None;
+        self.error = None;
+    }
+
+    // A save state of the running program; see `snapshot` for what it holds
+    pub fn save_state(&self) -> Vec<u8> {
+        Snapshot {
+            memory: self.memory,
+            reg: self.reg,
+            index_reg: self.index_reg,
+            program_counter: self.program_counter,
+            stack: self.stack.clone(),
+            stack_pointer: self.stack_pointer,
+            display_rows: self.display_rows,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            is_beeping: self.is_beeping,
+            keys: self.keys,
+            is_waiting_for_key: self.is_waiting_for_key,
+            reg_index_key_waiting: self.reg_index_key_waiting,
+            is_waiting_for_vblank: self.is_waiting_for_vblank,
+            is_rom_loaded: self.is_rom_loaded,
+            instruction_carry: self.instruction_carry,
+            time_budget: self.time_budget,
+            frame_position: self.frame_position,
+            error: self.error,
+            rng: self.rand_rng.clone(),
+        }
+        .encode()
+    }
+
+    // Restores a state from `save_state`. Returns false and leaves the machine
+    // alone if `state` cannot be read.
+    pub fn load_state(&mut self, state: &[u8]) -> bool {
+        let snapshot = match Snapshot::decode(state) {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.memory = snapshot.memory;
+        self.clear_code_caches();
+        self.reg = snapshot.reg;
+        self.index_reg = snapshot.index_reg;
+        self.program_counter = snapshot.program_counter;
+        self.stack = snapshot.stack;
+        self.stack_pointer = snapshot.stack_pointer;
+        self.display_rows = snapshot.display_rows;
+        self.stale_rows = ALL_ROWS;
+        self.unpack_stale_rows();
+        self.phosphor.restart(&self.frame_buffer);
+        self.mark_dirty(DirtyRegion::FULL_SCREEN);
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.is_beeping = snapshot.is_beeping;
+        self.keys = snapshot.keys;
+        self.is_waiting_for_key = snapshot.is_waiting_for_key;
+        self.reg_index_key_waiting = snapshot.reg_index_key_waiting;
+        self.is_waiting_for_vblank = snapshot.is_waiting_for_vblank;
+        self.is_rom_loaded = snapshot.is_rom_loaded;
+        self.instruction_carry = snapshot.instruction_carry;
+        self.time_budget = snapshot.time_budget;
+        self.frame_position = snapshot.frame_position;
+        self.error = snapshot.error;
+        self.rand_rng = snapshot.rng;
+        true
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Quirks survive `reset` so a profile can be chosen before loading a ROM
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        if !quirks.display_wait {
+            self.is_waiting_for_vblank = false;
+        }
+    }
+
+    // When enabled, `load_rom` replaces the quirks with the detected profile
+    pub fn set_auto_detect(&mut self, enabled: bool) {
+        self.auto_detect = enabled;
+    }
+
+    pub fn get_platform_guess(&self) -> Option<PlatformGuess> {
+        self.platform_guess
+    }
+
+    pub fn get_rom_info(&self) -> Option<RomInfo> {
+        self.rom_info
+    }
+
+    pub fn get_instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    // Like quirks, the speed survives `reset`. Only used in fixed timing.
+    // Capped at `timing::MAX_INSTRUCTIONS_PER_SECOND`.
+    pub fn set_instructions_per_second(&mut self, instructions: u32) {
+        self.instructions_per_second = instructions.min(timing::MAX_INSTRUCTIONS_PER_SECOND);
+        self.instruction_carry = 0;
+    }
+
+    pub fn get_ticks_per_frame(&self) -> usize {
+        (self.instructions_per_second / FRAMES_PER_SECOND) as usize
+    }
+
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        let max_ticks = (timing::MAX_INSTRUCTIONS_PER_SECOND / FRAMES_PER_SECOND) as usize;
+        self.set_instructions_per_second(ticks.min(max_ticks) as u32 * FRAMES_PER_SECOND);
+    }
+
+    pub fn get_timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    pub fn get_stack_limit(&self) -> Option<usize> {
+        self.stack_limit
+    }
+
+    // Calls nested deeper than this fault with a stack overflow; None lifts
+    // the limit, though the in-memory stack still only has room for 24
+    pub fn set_stack_limit(&mut self, limit: Option<usize>) {
+        self.stack_limit = limit;
+    }
+
+    pub fn is_stack_in_memory(&self) -> bool {
+        self.stack_in_memory
+    }
+
+    // Moves the call stack into RAM at 0xEA0, where ROMs can read and
+    // overwrite it, or back out again. Pending returns are carried over; if
+    // more are pending than fit in RAM, nothing changes and false is returned.
+    pub fn set_stack_in_memory(&mut self, enabled: bool) -> bool {
+        if enabled == self.stack_in_memory {
+            return true;
+        }
+        if enabled && self.stack_depth() > MEMORY_STACK_ENTRIES {
+            return false;
+        }
+        let mut entries = Vec::new();
+        while let Some(address) = self.pop_return_address() {
+            entries.push(address);
+        }
+        self.stack_in_memory = enabled;
+        for &address in entries.iter().rev() {
+            self.push_return_address(address);
+        }
+        true
+    }
+
+    pub fn is_vip_layout(&self) -> bool {
+        self.vip_layout
+    }
+
+    // Maps the display into RAM at 0xF00 as on the COSMAC VIP, so ROMs can
+    // read the screen and draw by writing to memory
+    pub fn set_vip_layout(&mut self, enabled: bool) {
+        self.vip_layout = enabled;
+        if enabled {
+            self.sync_display_to_memory();
+        }
+    }
+
+    pub fn get_execution_engine(&self) -> ExecutionEngine {
+        self.engine
+    }
+
+    // `CachedDecode` and `BlockJit` reuse work done for code that already
+    // ran. The interpreter's own writes keep them up to date; call
+    // `invalidate_decode_cache` after changing memory from outside.
+    pub fn set_execution_engine(&mut self, engine: ExecutionEngine) {
+        self.engine = engine;
+        self.clear_code_caches();
+    }
+
+    pub fn invalidate_decode_cache(&mut self) {
+        self.clear_code_caches();
+    }
+
+    fn clear_code_caches(&mut self) {
+        self.decode_cache.clear();
+        self.block_cache.clear();
+    }
+
+    // Called after the machine itself changed `start..end`
+    fn code_written(&mut self, start: usize, end: usize) {
+        self.decode_cache.invalidate(start, end);
+        self.block_cache.invalidate(start, end);
+        let (written_start, written_end) = self.memory_written;
+        self.memory_written = (written_start.min(start), written_end.max(end));
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protect
+    }
+
+    // Makes writes to the interpreter area and font fault with
+    // `WriteProtected` instead of silently corrupting them
+    pub fn set_write_protection(&mut self, enabled: bool) {
+        self.write_protect = enabled;
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.memory_regions().len()
+    }
+
+    pub fn region(&self, index: usize) -> Option<MemoryRegion> {
+        self.memory_regions().get(index).copied()
+    }
+
+    pub fn region_at(&self, address: usize) -> Option<MemoryRegion> {
+        self.memory_regions()
+            .into_iter()
+            .find(|region| region.contains(address))
+    }
+
+    pub fn set_timing_mode(&mut self, mode: TimingMode) {
+        self.timing_mode = mode;
+        self.time_budget = 0;
+        self.frame_position = 0;
+        self.is_waiting_for_vblank = false;
     }
 
     pub fn get_width(&self) -> usize {
@@ -165,22 +614,40 @@ impl Chip8 {
     pub fn get_height(&self) -> usize {
         FRAME_BUF_HEIGHT
     }
-    // true if collision otherwise false
-    fn xor_pixel(&mut self, x: usize, y: usize, val: u8) -> bool {
-        // 64 x 32: index = (row * width) + column = (y * FRAME_BUF_WIDTH) + x
-        let wrapped_x = x.rem_euclid(FRAME_BUF_WIDTH);
-        let wrapped_y = y.rem_euclid(FRAME_BUF_HEIGHT);
-        let index: usize = (wrapped_y * FRAME_BUF_WIDTH) + wrapped_x;
-        let start_val = self.frame_buffer[index];
+    // Flips the pixels of row `y` set in `bits`. Returns true if any of them
+    // was lit, i.e. on collision.
+    fn xor_row(&mut self, y: usize, bits: DisplayRow) -> bool {
+        let collision = self.display_rows[y] & bits != 0;
+        self.display_rows[y] ^= bits;
+        if let Some(region) = DirtyRegion::row(y, bits) {
+            self.stale_rows |= 1 << y;
+            self.mark_dirty(region);
+        }
+        collision
+    }
 
-        self.frame_buffer[index] ^= val;
-        if start_val == 1 && self.frame_buffer[index] == 0 {
-            return true;
+    fn unpack_stale_rows(&mut self) {
+        while self.stale_rows != 0 {
+            let y = self.stale_rows.trailing_zeros() as usize;
+            self.unpack_row(y);
+            self.stale_rows &= self.stale_rows - 1;
+        }
+    }
+
+    fn unpack_row(&mut self, y: usize) {
+        let row = self.display_rows[y];
+        let pixels = &mut self.frame_buffer[y * FRAME_BUF_WIDTH..(y + 1) * FRAME_BUF_WIDTH];
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = (row >> (FRAME_BUF_WIDTH - 1 - x)) as Pixel & 1;
         }
-        false
     }
 
     pub fn handle_opcode(&mut self, opcode: u16) {
+        self.dispatch(opcode);
+        self.unpack_stale_rows();
+    }
+
+    fn dispatch(&mut self, opcode: u16) {
         match opcode & 0xF000 {
             0x0000 => self.sys_addr(opcode),
             0x1000 => self.jp_addr(opcode),
@@ -198,39 +665,353 @@ impl Chip8 {
             0xD000 => self.display_sprite(opcode),
             0xE000 => self.skip_if_key_state(opcode),
             0xF000 => self.misc(opcode),
-            _ => unhandled_opcode_panic(opcode),
+            _ => self.fault(Chip8Error::UnhandledOpcode(opcode)),
+        }
+    }
+
+    // Records the first fault and stops execution until the next reset
+    fn fault(&mut self, error: Chip8Error) {
+        console_log!("ERROR: {}", error);
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    fn check_memory_range(&mut self, start: usize, len: usize) -> bool {
+        if start + len > MEM_MAX {
+            self.fault(Chip8Error::MemoryOutOfBounds {
+                address: start + len - 1,
+            });
+            return false;
+        }
+        true
+    }
+
+    // Writes `bytes` at `start` on behalf of the ROM. Returns false, after
+    // faulting, if the write was refused.
+    fn store(&mut self, start: usize, bytes: &[u8]) -> bool {
+        if !self.check_memory_range(start, bytes.len()) {
+            return false;
+        }
+        if self.write_protect && start < START_OF_ROM {
+            self.fault(Chip8Error::WriteProtected { address: start });
+            return false;
+        }
+        let end = start + bytes.len();
+        self.memory[start..end].copy_from_slice(bytes);
+        self.code_written(start, end);
+        if self.vip_layout && end > DISPLAY_START {
+            self.sync_memory_to_display();
+            self.unpack_stale_rows();
+        }
+        true
+    }
+
+    // The display area holds the packed rows as they are, big-endian
+    fn sync_display_to_memory(&mut self) {
+        for (bytes, row) in self.memory[DISPLAY_START..DISPLAY_END]
+            .chunks_exact_mut(FRAME_BUF_WIDTH / 8)
+            .zip(self.display_rows.iter())
+        {
+            bytes.copy_from_slice(&row.to_be_bytes());
+        }
+        self.code_written(DISPLAY_START, DISPLAY_END);
+    }
+
+    fn sync_memory_to_display(&mut self) {
+        for y in 0..FRAME_BUF_HEIGHT {
+            let start = DISPLAY_START + y * FRAME_BUF_WIDTH / 8;
+            let mut bytes = [0; FRAME_BUF_WIDTH / 8];
+            bytes.copy_from_slice(&self.memory[start..start + FRAME_BUF_WIDTH / 8]);
+            self.display_rows[y] = DisplayRow::from_be_bytes(bytes);
         }
+        self.stale_rows = ALL_ROWS;
+        self.mark_dirty(DirtyRegion::FULL_SCREEN);
+    }
+
+    fn mark_dirty(&mut self, region: DirtyRegion) {
+        self.dirty_region = Some(match self.dirty_region {
+            Some(dirty) => dirty.union(&region),
+            None => region,
+        });
+        self.frame_changed = true;
+        self.rgba_stale = true;
+        self.upscaled_stale = true;
+    }
+
+    // Part of the screen that changed since the previous call, if any
+    pub fn take_dirty_region(&mut self) -> Option<DirtyRegion> {
+        self.dirty_region.take()
+    }
+
+    // Feeds the finished frame to the flicker filter and signals the host if
+    // the screen changed
+    fn present_frame(&mut self) {
+        self.unpack_stale_rows();
+        if self.phosphor.mode != FlickerFilter::Off && self.phosphor.end_frame(&self.frame_buffer) {
+            self.mark_dirty(DirtyRegion::FULL_SCREEN);
+        }
+        if self.frame_changed {
+            self.frame_changed = false;
+            update_canvas();
+        }
+    }
+
+    pub fn get_error(&self) -> Option<String> {
+        self.error.map(|error| error.to_string())
+    }
+
+    fn stack_depth(&self) -> usize {
+        if self.stack_in_memory {
+            self.stack_pointer
+        } else {
+            self.stack.len()
+        }
+    }
+
+    fn push_return_address(&mut self, address: usize) {
+        if !self.stack_in_memory {
+            self.stack.push(address);
+            return;
+        }
+        let slot = MEMORY_STACK_START + self.stack_pointer * 2;
+        self.memory[slot] = (address >> 8) as u8;
+        self.memory[slot + 1] = address as u8;
+        self.code_written(slot, slot + 2);
+        self.stack_pointer += 1;
+    }
+
+    fn pop_return_address(&mut self) -> Option<usize> {
+        if !self.stack_in_memory {
+            return self.stack.pop();
+        }
+        if self.stack_pointer == 0 {
+            return None;
+        }
+        self.stack_pointer -= 1;
+        let slot = MEMORY_STACK_START + self.stack_pointer * 2;
+        Some((self.memory[slot] as usize) << 8 | self.memory[slot + 1] as usize)
+    }
+
+    fn fetch_opcode(&self) -> Option<u16> {
+        if self.program_counter + 1 >= MEM_MAX {
+            return None;
+        }
+        Some(
+            (self.memory[self.program_counter] as u16) << 8
+                | self.memory[self.program_counter + 1] as u16,
+        )
     }
 
     fn execute_instructions(&mut self) {
-        let opcode = (self.memory[self.program_counter] as u16) << 8
-            | self.memory[self.program_counter + 1] as u16;
+        if self.engine == ExecutionEngine::CachedDecode {
+            self.execute_cached();
+            return;
+        }
+        let opcode = match self.fetch_opcode() {
+            Some(opcode) => opcode,
+            None => {
+                self.fault(Chip8Error::ProgramCounterOutOfBounds {
+                    address: self.program_counter,
+                });
+                return;
+            }
+        };
         self.program_counter += 2;
-        self.handle_opcode(opcode);
+        self.dispatch(opcode);
     }
 
+    fn execute_cached(&mut self) {
+        let address = self.program_counter;
+        let op = match self.decode_cache.get(address) {
+            Some(op) => op,
+            None => match self.fetch_opcode() {
+                Some(opcode) => {
+                    let op = decode::decode(opcode);
+                    self.decode_cache.insert(address, op);
+                    op
+                }
+                None => {
+                    self.fault(Chip8Error::ProgramCounterOutOfBounds { address });
+                    return;
+                }
+            },
+        };
+        self.program_counter += 2;
+        (op.handler)(self, op.opcode);
+    }
+
+    fn can_execute(&self) -> bool {
+        !self.is_waiting_for_key
+            && !self.is_waiting_for_vblank
+            && self.is_rom_loaded
+            && self.error.is_none()
+    }
+
+    // One instruction followed by a timer decrement, as the machine has
+    // always stepped. `run_frame` runs whole frames at the configured speed.
     pub fn tick(&mut self) {
-        if !self.is_waiting_for_key && self.is_rom_loaded {
+        if self.can_execute() {
             self.execute_instructions();
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
+            self.tick_timers();
+        }
+        // The timer decrement makes each tick a frame, so it also ends a
+        // display wait and hosts that only call `tick` never stall
+        self.is_waiting_for_vblank = false;
+        self.unpack_stale_rows();
+    }
+
+    // One 60Hz frame: a batch of instructions followed by a single timer decrement
+    pub fn run_frame(&mut self) {
+        match self.timing_mode {
+            TimingMode::Fixed => self.run_fixed_frame(),
+            TimingMode::CosmacVip => self.run_vip(UNITS_PER_FRAME),
+        }
+        self.unpack_stale_rows();
+    }
+
+    // Advances emulated time, running whole frames in fixed timing and single
+    // instructions in VIP timing. Time that is too short for the next step is
+    // kept for the following call.
+    pub fn run_for(&mut self, duration_micros: u32) {
+        let units = duration_micros as u64 * UNITS_PER_MICRO;
+        match self.timing_mode {
+            TimingMode::Fixed => {
+                self.time_budget += units;
+                while self.time_budget >= UNITS_PER_FRAME {
+                    self.time_budget -= UNITS_PER_FRAME;
+                    self.run_fixed_frame();
+                }
             }
+            TimingMode::CosmacVip => self.run_vip(units),
+        }
+        self.unpack_stale_rows();
+    }
 
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-                play_beep();
+    fn run_fixed_frame(&mut self) {
+        self.instruction_carry += self.instructions_per_second;
+        let mut count = self.instruction_carry / FRAMES_PER_SECOND;
+        self.instruction_carry %= FRAMES_PER_SECOND;
+        // A stalled machine stays stalled until the frame ends
+        while count > 0 && self.can_execute() {
+            let ran = self.run_block(count);
+            if ran == 0 {
+                self.execute_instructions();
+                count -= 1;
+            } else {
+                count -= ran;
             }
         }
+        self.end_frame();
+    }
+
+    // Runs compiled blocks from the program counter, one after another, for
+    // as long as the engine has one that fits in what is left of `budget`.
+    // Returns how many instructions ran.
+    fn run_block(&mut self, budget: u32) -> u32 {
+        if self.engine != ExecutionEngine::BlockJit {
+            return 0;
+        }
+        let mut blocks = self.block_cache.lend();
+        let mut ran = 0;
+        while self.can_execute() {
+            let pc = self.program_counter;
+            let block = match self.block_cache.lookup(&mut blocks, pc, &self.memory) {
+                Some(block) => block,
+                None => break,
+            };
+            let len = block.len() as u32;
+            if len == 0 || len > budget - ran {
+                break;
+            }
+            ran += len;
+            if let Some(exit) = block.run(self) {
+                self.program_counter += 2;
+                (exit.handler)(self, exit.opcode);
+            }
+        }
+        self.block_cache.restore(blocks);
+        ran
+    }
+
+    fn run_vip(&mut self, units: u64) {
+        self.time_budget += units;
+        loop {
+            if !self.can_execute() {
+                // Idle until the next frame, which may end a display wait
+                let until_frame_end = UNITS_PER_FRAME - self.frame_position;
+                if self.time_budget < until_frame_end {
+                    self.frame_position += self.time_budget;
+                    self.time_budget = 0;
+                    return;
+                }
+                self.time_budget -= until_frame_end;
+                self.frame_position = 0;
+                self.end_frame();
+                continue;
+            }
+
+            let cost = match self.fetch_opcode() {
+                Some(opcode) => {
+                    timing::vip_cost_micros(opcode, self.reg[get_x(opcode)]) * UNITS_PER_MICRO
+                }
+                None => 0,
+            };
+            if cost > self.time_budget {
+                return;
+            }
+            self.time_budget -= cost;
+            self.execute_instructions();
+
+            // Frames that pass during a long instruction only tick the timers;
+            // a sprite drawn across a vertical blank waits for the next one
+            self.frame_position += cost;
+            while self.frame_position >= UNITS_PER_FRAME {
+                self.frame_position -= UNITS_PER_FRAME;
+                if self.is_rom_loaded {
+                    self.tick_timers();
+                }
+                self.present_frame();
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.is_waiting_for_vblank = false;
+        self.present_frame();
+        if self.is_rom_loaded {
+            self.tick_timers();
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        self.is_beeping = self.sound_timer > 0;
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            play_beep();
+        }
     }
     // 0x0000
     fn sys_addr(&mut self, opcode: u16) {
         match opcode {
             0x00E0 => {
+                self.display_rows.fill(0);
                 self.frame_buffer.fill(0);
-                update_canvas();
+                self.stale_rows = 0;
+                if self.vip_layout {
+                    self.sync_display_to_memory();
+                }
+                self.mark_dirty(DirtyRegion::FULL_SCREEN);
             }
-            0x00EE => self.program_counter = self.stack.pop().unwrap(),
-            _ => unhandled_opcode_panic(opcode),
+            0x00EE => match self.pop_return_address() {
+                Some(address) => self.program_counter = address,
+                None => self.fault(Chip8Error::StackUnderflow),
+            },
+            _ => self.fault(Chip8Error::UnhandledOpcode(opcode)),
         }
     }
     // 0x1000
@@ -239,7 +1020,14 @@ impl Chip8 {
     }
     // 0x2000
     fn call_addr(&mut self, opcode: u16) {
-        self.stack.push(self.program_counter);
+        let depth = self.stack_depth();
+        let full = self.stack_limit.is_some_and(|limit| depth >= limit)
+            || (self.stack_in_memory && depth >= MEMORY_STACK_ENTRIES);
+        if full {
+            self.fault(Chip8Error::StackOverflow);
+            return;
+        }
+        self.push_return_address(self.program_counter);
         self.program_counter = get_nnn(opcode) as usize;
     }
     // 0x3000
@@ -283,9 +1071,16 @@ impl Chip8 {
 
         match op {
             0x0 => self.reg[x] = self.reg[y],
-            0x1 => self.reg[x] |= self.reg[y],
-            0x2 => self.reg[x] &= self.reg[y],
-            0x3 => self.reg[x] ^= self.reg[y],
+            0x1..=0x3 => {
+                match op {
+                    0x1 => self.reg[x] |= self.reg[y],
+                    0x2 => self.reg[x] &= self.reg[y],
+                    _ => self.reg[x] ^= self.reg[y],
+                }
+                if self.quirks.vf_reset {
+                    self.reg[0xF] = 0;
+                }
+            }
             0x4 => {
                 let (result, carry) = self.reg[x].overflowing_add(self.reg[y]);
                 self.reg[x] = result;
@@ -297,8 +1092,9 @@ impl Chip8 {
                 self.reg[0xF] = !borrow as u8;
             }
             0x6 => {
-                self.reg[0xF] = (self.reg[x] & 1 == 1) as u8;
-                self.reg[x] >>= 1;
+                let value = self.shift_source(x, y);
+                self.reg[x] = value >> 1;
+                self.reg[0xF] = value & 1;
             }
             0x7 => {
                 let (result, borrow) = self.reg[y].overflowing_sub(self.reg[x]);
@@ -307,10 +1103,18 @@ impl Chip8 {
             }
             0xE => {
                 // 0b1000 0000
-                self.reg[0xF] = self.reg[x] >> 7;
-                self.reg[x] <<= 1;
+                let value = self.shift_source(x, y);
+                self.reg[x] = value << 1;
+                self.reg[0xF] = value >> 7;
             }
-            _ => unhandled_opcode_panic(opcode),
+            _ => self.fault(Chip8Error::UnhandledOpcode(opcode)),
+        }
+    }
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.reg[y]
+        } else {
+            self.reg[x]
         }
     }
     // 0xA000
@@ -319,7 +1123,12 @@ impl Chip8 {
     }
     // 0xB000
     fn jp_offset(&mut self, opcode: u16) {
-        self.program_counter = get_nnn(opcode) as usize + self.reg[0] as usize;
+        let offset_reg = if self.quirks.jump_uses_vx {
+            get_x(opcode)
+        } else {
+            0
+        };
+        self.program_counter = get_nnn(opcode) as usize + self.reg[offset_reg] as usize;
     }
     // 0xC000
     fn rand(&mut self, opcode: u16) {
@@ -330,35 +1139,56 @@ impl Chip8 {
         let bytes = opcode & 0xF;
         let (reg_x, reg_y) = (self.reg[get_x(opcode)], self.reg[get_y(opcode)]);
 
+        if !self.check_memory_range(self.index_reg as usize, bytes as usize) {
+            return;
+        }
+
         // Initialize collision flag to 0
         self.reg[0xF] = 0;
 
-        // Each byte represents a row of 8 pixels
-        // Outer loop: rows (y-direction)
-        // Inner loop: columns within each row (x-direction)
-        let mut pixel_y = reg_y;
+        // The starting position always wraps; the rest of the sprite either
+        // wraps too or is clipped at the edges, depending on the quirk
+        let start_x = reg_x as usize % FRAME_BUF_WIDTH;
+        let start_y = reg_y as usize % FRAME_BUF_HEIGHT;
+        let clipping = self.quirks.clipping;
 
-        for mem_index in (self.index_reg as usize)..(self.index_reg + bytes) as usize {
-            let mut pixel_x = reg_x;
-
-            for i in 0..8 {
-                // Extract bit from left to right (MSB to LSB)
-                let bit = (self.memory[mem_index] >> (7 - i)) & 1;
-
-                if self.xor_pixel(pixel_x as usize, pixel_y as usize, bit) {
-                    self.reg[0xF] = 1;
+        // Each byte is a row of 8 pixels. Moved to the left edge of a display
+        // row and then right to `start_x`, bits that fall off the right edge
+        // are the ones that would wrap around to the left.
+        for (row, mem_index) in
+            ((self.index_reg as usize)..(self.index_reg + bytes) as usize).enumerate()
+        {
+            let mut pixel_y = start_y + row;
+            if pixel_y >= FRAME_BUF_HEIGHT {
+                if clipping {
+                    break;
                 }
+                pixel_y -= FRAME_BUF_HEIGHT;
+            }
 
-                pixel_x += 1;
+            let sprite = (self.memory[mem_index] as DisplayRow) << (FRAME_BUF_WIDTH - 8);
+            let mut bits = sprite >> start_x;
+            if !clipping {
+                bits |= sprite
+                    .checked_shl((FRAME_BUF_WIDTH - start_x) as u32)
+                    .unwrap_or(0);
             }
-            pixel_y += 1;
+            if self.xor_row(pixel_y, bits) {
+                self.reg[0xF] = 1;
+            }
+        }
+        if self.quirks.display_wait {
+            self.is_waiting_for_vblank = true;
+        }
+        if self.vip_layout {
+            self.sync_display_to_memory();
         }
-        update_canvas();
     }
     // 0xE000
     fn skip_if_key_state(&mut self, opcode: u16) {
         let x = get_x(opcode);
-        let is_pressed = self.keys & (1 << self.reg[x]) != 0;
+        // Only the low nibble selects a key, as on the VIP
+        let is_pressed = self.keys & (1 << (self.reg[x] & 0xF)) != 0;
         // pressed
         if opcode & 0xFF == 0x9E {
             if is_pressed {
@@ -371,7 +1201,7 @@ impl Chip8 {
                 self.program_counter += 2;
             }
         } else {
-            unhandled_opcode_panic(opcode);
+            self.fault(Chip8Error::UnhandledOpcode(opcode));
         }
     }
     // 0xF000
@@ -383,28 +1213,40 @@ impl Chip8 {
             0x07 => self.reg[x] = self.delay_timer,
             0x0A => {
                 self.is_waiting_for_key = true;
+                self.reg_index_key_waiting = x;
             }
             0x15 => self.delay_timer = self.reg[x],
             0x18 => self.sound_timer = self.reg[x],
-            0x1E => self.index_reg += self.reg[x] as u16,
+            0x1E => self.index_reg = self.index_reg.wrapping_add(self.reg[x] as u16),
             0x29 => self.index_reg = START_OF_FONT as u16 + (self.reg[x] as u16 * 5),
             0x33 => {
-                self.memory[self.index_reg as usize] = self.reg[x] / 100;
-                self.memory[self.index_reg as usize + 1] = (self.reg[x] / 10) % 10;
-                self.memory[self.index_reg as usize + 2] = self.reg[x] % 10;
+                let value = self.reg[x];
+                self.store(
+                    self.index_reg as usize,
+                    &[value / 100, (value / 10) % 10, value % 10],
+                );
             }
             0x55 => {
-                self.memory[(self.index_reg as usize)..=(self.index_reg as usize + x)]
-                    .copy_from_slice(&self.reg[0..=x]);
-                self.index_reg += (x + 1) as u16;
+                let reg = self.reg;
+                if !self.store(self.index_reg as usize, &reg[0..=x]) {
+                    return;
+                }
+                if self.quirks.memory_increment {
+                    self.index_reg += (x + 1) as u16;
+                }
             }
             0x65 => {
+                if !self.check_memory_range(self.index_reg as usize, x + 1) {
+                    return;
+                }
                 self.reg[0..=x].copy_from_slice(
                     &self.memory[(self.index_reg as usize)..=(self.index_reg as usize + x)],
                 );
-                self.index_reg += (x + 1) as u16;
+                if self.quirks.memory_increment {
+                    self.index_reg += (x + 1) as u16;
+                }
             }
-            _ => unhandled_opcode_panic(opcode),
+            _ => self.fault(Chip8Error::UnhandledOpcode(opcode)),
         }
     }
 
@@ -412,15 +1254,102 @@ impl Chip8 {
         self.frame_buffer.as_ptr()
     }
 
+    // The screen as RGBA8, four bytes per pixel in the current palette
+    pub fn get_rgba_screen(&mut self) -> *const u8 {
+        self.rgba_screen().as_ptr()
+    }
+
+    pub fn get_palette(&self) -> Vec<u32> {
+        self.palette.clone()
+    }
+
+    pub fn set_palette(&mut self, palette: BuiltinPalette) {
+        self.set_custom_palette(palette.colors());
+    }
+
+    // Colors as 0xRRGGBB, background first. Returns false and keeps the
+    // current palette if fewer than two colors are given.
+    pub fn set_custom_palette(&mut self, colors: &[u32]) -> bool {
+        if colors.len() < 2 {
+            return false;
+        }
+        self.palette = colors.iter().map(|color| color & 0xFF_FFFF).collect();
+        self.rgba_stale = true;
+        self.upscaled_stale = true;
+        true
+    }
+
+    pub fn get_flicker_filter(&self) -> FlickerFilter {
+        self.phosphor.mode
+    }
+
+    // With a filter on, the RGBA screen shows the filtered image, updated
+    // once per frame
+    pub fn set_flicker_filter(&mut self, filter: FlickerFilter) {
+        self.phosphor.mode = filter;
+        self.phosphor.restart(&self.frame_buffer);
+        self.mark_dirty(DirtyRegion::FULL_SCREEN);
+    }
+
+    pub fn get_phosphor_decay(&self) -> f32 {
+        self.phosphor.decay()
+    }
+
+    pub fn set_phosphor_decay(&mut self, decay: f32) {
+        self.phosphor.set_decay(decay);
+    }
+
+    // Brightness of each pixel after filtering, one byte per pixel from 0
+    // to 255. Only meaningful while a filter is on.
+    pub fn get_filtered_screen(&self) -> *const u8 {
+        self.phosphor.intensity().as_ptr()
+    }
+
+    pub fn get_upscaler(&self) -> Upscaler {
+        self.upscaler
+    }
+
+    pub fn get_upscale_factor(&self) -> usize {
+        self.upscale_factor
+    }
+
+    // `scale` is clamped to 1-16. Scanlines and the shadow mask of `Crt`
+    // need at least 2.
+    pub fn set_upscaler(&mut self, upscaler: Upscaler, scale: usize) {
+        self.upscaler = upscaler;
+        self.upscale_factor = scale.clamp(1, upscale::MAX_SCALE);
+        self.upscaled_stale = true;
+    }
+
+    // The RGBA screen after upscaling, `get_upscaled_width()` pixels wide
+    pub fn get_upscaled_screen(&mut self) -> *const u8 {
+        self.upscaled_screen().as_ptr()
+    }
+
+    pub fn get_upscaled_width(&self) -> usize {
+        FRAME_BUF_WIDTH * self.upscale_factor
+    }
+
+    pub fn get_upscaled_height(&self) -> usize {
+        FRAME_BUF_HEIGHT * self.upscale_factor
+    }
+
     pub fn get_memory(&self) -> *const u8 {
         self.memory.as_ptr()
     }
 
+    pub fn is_beeping(&self) -> bool {
+        self.is_beeping
+    }
+
     pub fn get_keys(&self) -> u16 {
         self.keys
     }
 
     pub fn set_key(&mut self, index: u8) {
+        if index as usize >= KEY_COUNT {
+            return;
+        }
         self.keys |= 1 << index as u16;
         if self.is_waiting_for_key {
             self.is_waiting_for_key = false;
@@ -429,10 +1358,136 @@ impl Chip8 {
     }
 
     pub fn unset_key(&mut self, index: u8) {
+        if index as usize >= KEY_COUNT {
+            return;
+        }
         self.keys &= !(1 << index as u16);
     }
 }
 
+// Native-only API, not exported to JavaScript
+impl Chip8 {
+    // Deterministic machine for tests and headless runs: Cxkk yields the same
+    // sequence for the same seed
+    pub fn with_seed(seed: u64) -> Self {
+        let mut chip8 = Self::new();
+        chip8.rand_rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        chip8
+    }
+
+    pub fn frame_buffer(&self) -> &[Pixel] {
+        &self.frame_buffer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Writes `bytes` at `start` from the host, e.g. to patch a ROM or set up
+    // a test. Write protection does not apply. False if the range does not
+    // fit in memory.
+    pub fn write_memory(&mut self, start: usize, bytes: &[u8]) -> bool {
+        let end = match start.checked_add(bytes.len()) {
+            Some(end) if end <= MEM_MAX => end,
+            _ => return false,
+        };
+        self.memory[start..end].copy_from_slice(bytes);
+        self.code_written(start, end);
+        if self.vip_layout && end > DISPLAY_START {
+            self.sync_memory_to_display();
+            self.unpack_stale_rows();
+        }
+        true
+    }
+
+    // V0-VF
+    pub fn registers(&self) -> &[u8] {
+        &self.reg
+    }
+
+    // Ignores indices past VF
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        if let Some(reg) = self.reg.get_mut(index) {
+            *reg = value;
+        }
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.index_reg
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Pending return addresses, oldest first, wherever the stack lives
+    pub fn stack(&self) -> Vec<usize> {
+        if !self.stack_in_memory {
+            return self.stack.clone();
+        }
+        self.memory[MEMORY_STACK_START..MEMORY_STACK_START + self.stack_pointer * 2]
+            .chunks(2)
+            .map(|slot| (slot[0] as usize) << 8 | slot[1] as usize)
+            .collect()
+    }
+
+    // The screen one row per word, bit 63 being the leftmost pixel
+    pub fn display_rows(&self) -> &[DisplayRow] {
+        &self.display_rows
+    }
+
+    pub fn filtered_screen(&self) -> &[u8] {
+        self.phosphor.intensity()
+    }
+
+    pub fn rgba_screen(&mut self) -> &[u8] {
+        if self.rgba_stale {
+            if self.phosphor.mode == FlickerFilter::Off {
+                palette::render_rgba(&self.frame_buffer, &self.palette, &mut self.rgba_screen);
+            } else {
+                filter::render_rgba(
+                    self.phosphor.intensity(),
+                    &self.palette,
+                    &mut self.rgba_screen,
+                );
+            }
+            self.rgba_stale = false;
+        }
+        &self.rgba_screen
+    }
+
+    pub fn upscaled_screen(&mut self) -> &[u8] {
+        if self.upscaled_stale {
+            self.rgba_screen();
+            self.upscaled_screen = upscale::upscale(
+                self.upscaler,
+                self.upscale_factor,
+                &self.rgba_screen,
+                FRAME_BUF_WIDTH,
+                FRAME_BUF_HEIGHT,
+            );
+            self.upscaled_stale = false;
+        }
+        &self.upscaled_screen
+    }
+
+    pub fn error(&self) -> Option<Chip8Error> {
+        self.error
+    }
+
+    pub fn memory_regions(&self) -> Vec<MemoryRegion> {
+        memory_map::regions(self.vip_layout, self.stack_in_memory, self.write_protect)
+    }
+}
+
 // This is like the `main` function, except for JavaScript.
 #[wasm_bindgen(start)]
 pub fn main_js() -> Result<(), JsValue> {
@@ -447,5 +1502,7 @@ pub fn main_js() -> Result<(), JsValue> {
     Ok(())
 }
 
+#[cfg(test)]
+mod reference;
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,103 @@
+// Layout of the 4K address space. The interpreter owns everything below
+// 0x200, with the font at 0x50. The stack at 0xEA0 only exists while it lives
+// in memory, and the display buffer at 0xF00 only under the VIP layout, where
+// the screen is read from RAM like on the original machine.
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    FRAME_BUF_HEIGHT, FRAME_BUF_WIDTH, MEMORY_STACK_END, MEMORY_STACK_START, MEM_MAX,
+    START_OF_FONT, START_OF_ROM,
+};
+
+pub const FONT_END: usize = START_OF_FONT + 16 * 5;
+// Where the VIP interpreter keeps its variables, and where ROMs had to end
+pub const VIP_RESERVED_START: usize = MEMORY_STACK_START;
+// One bit per pixel, a row of 64 pixels in 8 bytes
+pub const DISPLAY_START: usize = 0xF00;
+pub const DISPLAY_END: usize = DISPLAY_START + FRAME_BUF_WIDTH * FRAME_BUF_HEIGHT / 8;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    Interpreter,
+    Font,
+    Program,
+    Stack,
+    // VIP interpreter variables between the stack and the display
+    Variables,
+    Display,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub kind: RegionKind,
+    pub start: usize,
+    // Exclusive
+    pub end: usize,
+    // Writes by the ROM fault instead of landing
+    pub protected: bool,
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start..self.end).contains(&address)
+    }
+}
+
+// Regions in address order, covering all of memory
+pub fn regions(vip_layout: bool, stack_in_memory: bool, write_protect: bool) -> Vec<MemoryRegion> {
+    let region = |kind, start, end| MemoryRegion {
+        kind,
+        start,
+        end,
+        protected: write_protect && matches!(kind, RegionKind::Interpreter | RegionKind::Font),
+    };
+
+    let mut regions = vec![
+        region(RegionKind::Interpreter, 0, START_OF_FONT),
+        region(RegionKind::Font, START_OF_FONT, FONT_END),
+        region(RegionKind::Interpreter, FONT_END, START_OF_ROM),
+    ];
+    if vip_layout {
+        regions.push(region(
+            RegionKind::Program,
+            START_OF_ROM,
+            VIP_RESERVED_START,
+        ));
+        if stack_in_memory {
+            regions.push(region(
+                RegionKind::Stack,
+                MEMORY_STACK_START,
+                MEMORY_STACK_END,
+            ));
+            regions.push(region(
+                RegionKind::Variables,
+                MEMORY_STACK_END,
+                DISPLAY_START,
+            ));
+        } else {
+            regions.push(region(
+                RegionKind::Variables,
+                VIP_RESERVED_START,
+                DISPLAY_START,
+            ));
+        }
+        regions.push(region(RegionKind::Display, DISPLAY_START, DISPLAY_END));
+    } else if stack_in_memory {
+        regions.push(region(
+            RegionKind::Program,
+            START_OF_ROM,
+            MEMORY_STACK_START,
+        ));
+        regions.push(region(
+            RegionKind::Stack,
+            MEMORY_STACK_START,
+            MEMORY_STACK_END,
+        ));
+        regions.push(region(RegionKind::Program, MEMORY_STACK_END, MEM_MAX));
+    } else {
+        regions.push(region(RegionKind::Program, START_OF_ROM, MEM_MAX));
+    }
+    regions
+}
@@ -0,0 +1,94 @@
+// Compiled-in database of known ROMs, keyed by the SHA-1 of the ROM image.
+// The table is generated by `build.rs` from `roms.json`.
+use wasm_bindgen::prelude::*;
+
+use crate::{Platform, Quirks};
+
+pub struct RomEntry {
+    pub sha1: [u8; 20],
+    pub title: &'static str,
+    pub author: &'static str,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub ticks_per_frame: usize,
+    // Host key name (lower case, as in `KeyboardEvent.key`) to CHIP-8 key
+    pub keys: &'static [(&'static str, u8)],
+    // [background, foreground] as 0xRRGGBB
+    pub palette: [u32; 2],
+}
+
+include!(concat!(env!("OUT_DIR"), "/rom_database.rs"));
+
+pub fn find(rom: &[u8]) -> Option<&'static RomEntry> {
+    let sha1 = sha1_smol::Sha1::from(rom).digest().bytes();
+    ROM_DATABASE
+        .binary_search_by(|entry| entry.sha1.cmp(&sha1))
+        .ok()
+        .map(|index| &ROM_DATABASE[index])
+}
+
+// JavaScript view of a database entry
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct RomInfo {
+    entry: &'static RomEntry,
+}
+
+impl RomInfo {
+    pub fn entry(&self) -> &'static RomEntry {
+        self.entry
+    }
+}
+
+#[wasm_bindgen]
+impl RomInfo {
+    pub fn title(&self) -> String {
+        self.entry.title.to_string()
+    }
+
+    pub fn author(&self) -> String {
+        self.entry.author.to_string()
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.entry.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.entry.quirks
+    }
+
+    pub fn ticks_per_frame(&self) -> usize {
+        self.entry.ticks_per_frame
+    }
+
+    // Host keys this ROM suggests binding, in addition to the default layout
+    pub fn bound_keys(&self) -> Vec<String> {
+        self.entry
+            .keys
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    pub fn key_for(&self, host_key: &str) -> Option<u8> {
+        self.entry
+            .keys
+            .iter()
+            .find(|(key, _)| *key == host_key)
+            .map(|&(_, chip8_key)| chip8_key)
+    }
+
+    pub fn background_color(&self) -> u32 {
+        self.entry.palette[0]
+    }
+
+    pub fn foreground_color(&self) -> u32 {
+        self.entry.palette[1]
+    }
+}
+
+#[wasm_bindgen]
+pub fn lookup_rom(rom: &[u8]) -> Option<RomInfo> {
+    find(rom).map(|entry| RomInfo { entry })
+}
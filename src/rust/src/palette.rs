@@ -0,0 +1,41 @@
+// Screen colors as 0xRRGGBB, indexed by pixel value: background first, then
+// the color of each plane, then the color where planes overlap. CHIP-8 only
+// uses the first two; the rest are there for XO-CHIP's second plane.
+use wasm_bindgen::prelude::*;
+
+use crate::{Pixel, FRAME_BUF_MAX};
+
+pub const RGBA_SCREEN_LEN: usize = FRAME_BUF_MAX * 4;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinPalette {
+    Monochrome,
+    GreenPhosphor,
+    Amber,
+    // Octo's default colors
+    Octo,
+    // The original Game Boy's four shades of green
+    GameBoy,
+}
+
+impl BuiltinPalette {
+    pub fn colors(&self) -> &'static [u32] {
+        match self {
+            BuiltinPalette::Monochrome => &[0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            BuiltinPalette::GreenPhosphor => &[0x0C1A0C, 0x33FF66, 0x1F8F3F, 0x99FFB3],
+            BuiltinPalette::Amber => &[0x1A0F00, 0xFFB000, 0x8F6300, 0xFFD27F],
+            BuiltinPalette::Octo => &[0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            BuiltinPalette::GameBoy => &[0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F],
+        }
+    }
+}
+
+// Writes one opaque RGBA8 pixel per entry of `frame_buffer`. Values past the
+// end of the palette use its last color.
+pub fn render_rgba(frame_buffer: &[Pixel], colors: &[u32], rgba: &mut [u8]) {
+    for (&pixel, out) in frame_buffer.iter().zip(rgba.chunks_exact_mut(4)) {
+        let color = colors[(pixel as usize).min(colors.len() - 1)];
+        out.copy_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xFF]);
+    }
+}
@@ -0,0 +1,96 @@
+// Behavioural differences between CHIP-8 interpreters. Each flag selects the
+// variant of an instruction whose semantics changed across platforms.
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1/8xy2/8xy3 clear VF
+    pub vf_reset: bool,
+    // Fx55/Fx65 leave I pointing past the last register
+    pub memory_increment: bool,
+    // 8xy6/8xyE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // Bxnn jumps to xnn + VX instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // Sprites are cut off at the screen edge instead of wrapping around
+    pub clipping: bool,
+    // Dxyn waits for the next vertical blank, allowing at most one sprite
+    // per 60Hz frame
+    pub display_wait: bool,
+}
+
+// Named profiles, in the order they are offered to users
+pub const QUIRK_PROFILES: &[(&str, Quirks)] = &[
+    ("default", Quirks::DEFAULT),
+    ("chip8", Quirks::COSMAC_VIP),
+    ("schip", Quirks::SCHIP),
+    ("xochip", Quirks::XO_CHIP),
+];
+
+impl Quirks {
+    // What this emulator has always done; suits most modern CHIP-8 games
+    pub const DEFAULT: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: true,
+        shift_uses_vy: false,
+        jump_uses_vx: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    // Original COSMAC VIP interpreter
+    pub const COSMAC_VIP: Quirks = Quirks {
+        vf_reset: true,
+        memory_increment: true,
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        clipping: true,
+        display_wait: true,
+    };
+
+    // SUPER-CHIP 1.1 as found on the HP48
+    pub const SCHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: false,
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        clipping: true,
+        display_wait: false,
+    };
+
+    // Octo's XO-CHIP
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: true,
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        clipping: false,
+        display_wait: false,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::DEFAULT
+    }
+}
+
+#[wasm_bindgen]
+impl Quirks {
+    // Looks up one of the names in `QUIRK_PROFILES`
+    pub fn from_profile(name: &str) -> Option<Quirks> {
+        QUIRK_PROFILES
+            .iter()
+            .find(|(profile, _)| profile.eq_ignore_ascii_case(name))
+            .map(|&(_, quirks)| quirks)
+    }
+
+    // Name of the matching profile, if these flags are exactly one of them
+    pub fn profile_name(&self) -> Option<String> {
+        QUIRK_PROFILES
+            .iter()
+            .find(|(_, quirks)| quirks == self)
+            .map(|(name, _)| name.to_string())
+    }
+}
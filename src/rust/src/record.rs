@@ -0,0 +1,192 @@
+// Gameplay recorder: captures presented frames and encodes them as an
+// animated GIF or APNG clip.
+use std::{convert::TryInto, fmt};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{upscale::MAX_SCALE, Chip8, Pixel, FRAME_BUF_HEIGHT, FRAME_BUF_MAX, FRAME_BUF_WIDTH};
+
+const FRAMES_PER_SECOND: u32 = 60;
+// GIF frame delays are stored in hundredths of a second
+const GIF_TICKS_PER_SECOND: u32 = 100;
+// Browsers play shorter delays at 10cs, so faster frames are merged
+const GIF_MIN_DELAY: u32 = 2;
+
+const OFF_COLOR: [u8; 3] = [0x00, 0x00, 0x00];
+const ON_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+// A frame passed to `Recorder::push_frame` that is not one byte per pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameSizeError {
+    pub len: usize,
+}
+
+impl fmt::Display for FrameSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame has {} pixels instead of {}",
+            self.len, FRAME_BUF_MAX
+        )
+    }
+}
+
+impl std::error::Error for FrameSizeError {}
+
+struct RecordedFrame {
+    pixels: Vec<Pixel>,
+    // How many consecutive 60Hz frames showed this image
+    duration: u32,
+}
+
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Recorder {
+    frames: Vec<RecordedFrame>,
+}
+
+#[wasm_bindgen]
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Call once per presented frame; identical consecutive frames are merged
+    pub fn capture(&mut self, chip8: &Chip8) {
+        self.push_pixels(&chip8.frame_buffer);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // Number of distinct frames stored after deduplication
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Total recorded length in 60Hz frames
+    pub fn duration_frames(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    pub fn encode_gif(&self, scale: usize) -> Result<Vec<u8>, JsValue> {
+        self.try_encode_gif(scale)
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    pub fn encode_apng(&self, scale: usize) -> Result<Vec<u8>, JsValue> {
+        self.try_encode_apng(scale)
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+}
+
+impl Recorder {
+    // Adds a frame of one byte per pixel, as in `Chip8::frame_buffer`
+    pub fn push_frame(&mut self, pixels: &[Pixel]) -> Result<(), FrameSizeError> {
+        let pixels = pixels
+            .try_into()
+            .map_err(|_| FrameSizeError { len: pixels.len() })?;
+        self.push_pixels(pixels);
+        Ok(())
+    }
+
+    fn push_pixels(&mut self, pixels: &[Pixel; FRAME_BUF_MAX]) {
+        if let Some(last) = self.frames.last_mut() {
+            if last.pixels == pixels {
+                last.duration += 1;
+                return;
+            }
+        }
+        self.frames.push(RecordedFrame {
+            pixels: pixels.to_vec(),
+            duration: 1,
+        });
+    }
+
+    // `scale` is clamped to 1..=MAX_SCALE, as for `Chip8::set_upscaler`
+    pub fn try_encode_gif(&self, scale: usize) -> Result<Vec<u8>, gif::EncodingError> {
+        let scale = scale.clamp(1, MAX_SCALE);
+        let (width, height) = scaled_size(scale);
+        let palette = [OFF_COLOR, ON_COLOR].concat();
+        let mut out = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &palette)?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+
+            // 60Hz does not divide evenly into centiseconds, so delays are
+            // derived from the running timestamp to keep the clip from
+            // drifting. Frames are merged until the delay is long enough to
+            // play at speed, showing the one that was on screen longest.
+            let mut elapsed_frames = 0;
+            let mut start = 0;
+            let mut shown: Option<&RecordedFrame> = None;
+            for (index, frame) in self.frames.iter().enumerate() {
+                elapsed_frames += frame.duration;
+                if shown.is_none_or(|shown| frame.duration >= shown.duration) {
+                    shown = Some(frame);
+                }
+                let end = frames_to_gif_ticks(elapsed_frames);
+                let is_last = index + 1 == self.frames.len();
+                if end - start < GIF_MIN_DELAY && !is_last {
+                    continue;
+                }
+
+                let indexed = upscale(&shown.take().unwrap_or(frame).pixels, scale);
+                let mut gif_frame =
+                    gif::Frame::from_indexed_pixels(width as u16, height as u16, indexed, None);
+                gif_frame.delay = (end - start).clamp(GIF_MIN_DELAY, u16::MAX as u32) as u16;
+                encoder.write_frame(&gif_frame)?;
+                start = end;
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn try_encode_apng(&self, scale: usize) -> Result<Vec<u8>, png::EncodingError> {
+        let scale = scale.clamp(1, MAX_SCALE);
+        let (width, height) = scaled_size(scale);
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_palette([OFF_COLOR, ON_COLOR].concat());
+            encoder.set_animated(self.frames.len().max(1) as u32, 0)?;
+
+            let mut writer = encoder.write_header()?;
+            if self.frames.is_empty() {
+                writer.write_image_data(&vec![0; width * height])?;
+            }
+            for frame in &self.frames {
+                let delay = frame.duration.min(u16::MAX as u32) as u16;
+                writer.set_frame_delay(delay, FRAMES_PER_SECOND as u16)?;
+                writer.write_image_data(&upscale(&frame.pixels, scale))?;
+            }
+            writer.finish()?;
+        }
+        Ok(out)
+    }
+}
+
+fn frames_to_gif_ticks(frames: u32) -> u32 {
+    (frames * GIF_TICKS_PER_SECOND + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND
+}
+
+fn scaled_size(scale: usize) -> (usize, usize) {
+    (FRAME_BUF_WIDTH * scale, FRAME_BUF_HEIGHT * scale)
+}
+
+// Nearest-neighbour upscale producing one palette index per output pixel
+fn upscale(pixels: &[Pixel], scale: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(FRAME_BUF_MAX * scale * scale);
+    for row in pixels.chunks(FRAME_BUF_WIDTH) {
+        for _ in 0..scale {
+            for &pixel in row {
+                let index = (pixel != 0) as u8;
+                out.extend(std::iter::repeat_n(index, scale));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,200 @@
+// Deliberately simple model of the CHIP-8 instruction set, used to cross-check
+// `Chip8::handle_opcode`. Every instruction is a pure function from the old
+// state to a new one, written straight from the spec with no shared helpers,
+// so that a regression in the real interpreter shows up as a disagreement.
+use crate::{
+    Chip8, Quirks, DEFAULT_STACK_LIMIT, FRAME_BUF_HEIGHT, FRAME_BUF_WIDTH, MEM_MAX, START_OF_FONT,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub memory: Vec<u8>,
+    pub reg: [u8; 16],
+    pub index_reg: u16,
+    pub stack: Vec<usize>,
+    pub frame_buffer: Vec<u8>,
+    pub program_counter: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: u16,
+}
+
+impl State {
+    pub fn from_chip8(chip8: &Chip8) -> Self {
+        Self {
+            memory: chip8.memory.to_vec(),
+            reg: chip8.reg,
+            index_reg: chip8.index_reg,
+            stack: chip8.stack.clone(),
+            frame_buffer: chip8.frame_buffer.to_vec(),
+            program_counter: chip8.program_counter,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            keys: chip8.keys,
+        }
+    }
+
+    // Whether `opcode` is defined and stays inside memory and the stack for
+    // this state. The model has no answer for anything else.
+    pub fn can_execute(&self, opcode: u16) -> bool {
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let i = self.index_reg as usize;
+        match opcode >> 12 {
+            0x0 => opcode == 0x00E0 || (opcode == 0x00EE && !self.stack.is_empty()),
+            0x2 => self.stack.len() < DEFAULT_STACK_LIMIT,
+            0x5 | 0x9 => opcode & 0xF == 0,
+            0x8 => matches!(opcode & 0xF, 0x0..=0x7 | 0xE),
+            0xD => i + (opcode & 0xF) as usize <= MEM_MAX,
+            0xE => matches!(opcode & 0xFF, 0x9E | 0xA1) && self.reg[x] < 16,
+            0xF => match opcode & 0xFF {
+                0x07 | 0x15 | 0x18 | 0x1E | 0x29 => true,
+                0x33 => i + 2 < MEM_MAX,
+                0x55 | 0x65 => i + x < MEM_MAX,
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+// `random` is the byte the machine's RNG produces next; only Cxkk reads it
+pub fn step(old: &State, opcode: u16, quirks: Quirks, random: u8) -> State {
+    let mut s = old.clone();
+    let x = ((opcode >> 8) & 0xF) as usize;
+    let y = ((opcode >> 4) & 0xF) as usize;
+    let n = (opcode & 0xF) as usize;
+    let kk = (opcode & 0xFF) as u8;
+    let nnn = (opcode & 0xFFF) as usize;
+    let vx = old.reg[x] as u32;
+    let vy = old.reg[y] as u32;
+
+    match opcode >> 12 {
+        0x0 if opcode == 0x00E0 => s.frame_buffer = vec![0; old.frame_buffer.len()],
+        0x0 => s.program_counter = s.stack.pop().unwrap(),
+        0x1 => s.program_counter = nnn,
+        0x2 => {
+            s.stack.push(old.program_counter);
+            s.program_counter = nnn;
+        }
+        0x3 if vx == kk as u32 => s.program_counter += 2,
+        0x4 if vx != kk as u32 => s.program_counter += 2,
+        0x5 if vx == vy => s.program_counter += 2,
+        0x9 if vx != vy => s.program_counter += 2,
+        0x3 | 0x4 | 0x5 | 0x9 => {}
+        0x6 => s.reg[x] = kk,
+        0x7 => s.reg[x] = ((vx + kk as u32) % 256) as u8,
+        0x8 => {
+            let mut flag = None;
+            let result = match n {
+                0x0 => vy,
+                0x1..=0x3 => {
+                    if quirks.vf_reset {
+                        flag = Some(0);
+                    }
+                    match n {
+                        0x1 => vx | vy,
+                        0x2 => vx & vy,
+                        _ => vx ^ vy,
+                    }
+                }
+                0x4 => {
+                    flag = Some((vx + vy > 255) as u8);
+                    (vx + vy) % 256
+                }
+                0x5 => {
+                    flag = Some((vx >= vy) as u8);
+                    (vx + 256 - vy) % 256
+                }
+                0x7 => {
+                    flag = Some((vy >= vx) as u8);
+                    (vy + 256 - vx) % 256
+                }
+                0x6 => {
+                    let source = if quirks.shift_uses_vy { vy } else { vx };
+                    flag = Some((source % 2) as u8);
+                    source / 2
+                }
+                _ => {
+                    let source = if quirks.shift_uses_vy { vy } else { vx };
+                    flag = Some((source / 128) as u8);
+                    (source * 2) % 256
+                }
+            };
+            s.reg[x] = result as u8;
+            if let Some(flag) = flag {
+                s.reg[0xF] = flag;
+            }
+        }
+        0xA => s.index_reg = nnn as u16,
+        0xB => {
+            let offset = if quirks.jump_uses_vx {
+                vx
+            } else {
+                old.reg[0] as u32
+            };
+            s.program_counter = nnn + offset as usize;
+        }
+        0xC => s.reg[x] = random & kk,
+        0xD => {
+            let mut collision = 0;
+            for row in 0..n {
+                let sprite = old.memory[old.index_reg as usize + row];
+                for col in 0..8 {
+                    if sprite & (0x80 >> col) == 0 {
+                        continue;
+                    }
+                    let mut px = vx as usize % FRAME_BUF_WIDTH + col;
+                    let mut py = vy as usize % FRAME_BUF_HEIGHT + row;
+                    if px >= FRAME_BUF_WIDTH || py >= FRAME_BUF_HEIGHT {
+                        if quirks.clipping {
+                            continue;
+                        }
+                        px %= FRAME_BUF_WIDTH;
+                        py %= FRAME_BUF_HEIGHT;
+                    }
+                    let pixel = &mut s.frame_buffer[py * FRAME_BUF_WIDTH + px];
+                    if *pixel == 1 {
+                        collision = 1;
+                    }
+                    *pixel ^= 1;
+                }
+            }
+            s.reg[0xF] = collision;
+        }
+        0xE => {
+            let pressed = (old.keys >> vx) & 1 == 1;
+            if (kk == 0x9E && pressed) || (kk == 0xA1 && !pressed) {
+                s.program_counter += 2;
+            }
+        }
+        _ => {
+            let i = old.index_reg as usize;
+            match kk {
+                0x07 => s.reg[x] = old.delay_timer,
+                0x15 => s.delay_timer = vx as u8,
+                0x18 => s.sound_timer = vx as u8,
+                0x1E => s.index_reg = (i + vx as usize) as u16,
+                0x29 => s.index_reg = (START_OF_FONT + vx as usize * 5) as u16,
+                0x33 => {
+                    s.memory[i] = (vx / 100) as u8;
+                    s.memory[i + 1] = (vx / 10 % 10) as u8;
+                    s.memory[i + 2] = (vx % 10) as u8;
+                }
+                0x55 | 0x65 => {
+                    for r in 0..=x {
+                        if kk == 0x55 {
+                            s.memory[i + r] = old.reg[r];
+                        } else {
+                            s.reg[r] = old.memory[i + r];
+                        }
+                    }
+                    if quirks.memory_increment {
+                        s.index_reg = (i + x + 1) as u16;
+                    }
+                }
+                _ => unreachable!("opcode 0x{:04X} is not modelled", opcode),
+            }
+        }
+    }
+    s
+}
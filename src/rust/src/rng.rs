@@ -0,0 +1,83 @@
+// The random number generator behind Cxkk. It is the xoshiro256++ generator
+// `SmallRng` uses on 64-bit targets, seeded the same way, so seeds give the
+// same numbers as before. Keeping it here exposes its state for save states
+// and gives the wasm build the same sequence as native ones.
+use std::convert::TryInto;
+
+use rand::{Error, RngCore, SeedableRng};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Xoshiro256PlusPlus {
+    s: [u64; 4],
+}
+
+impl Xoshiro256PlusPlus {
+    pub fn state(&self) -> [u64; 4] {
+        self.s
+    }
+
+    // None for the all-zero state, which would only ever produce zeros
+    pub fn from_state(s: [u64; 4]) -> Option<Self> {
+        (s != [0; 4]).then_some(Self { s })
+    }
+}
+
+// `seed_from_u64` is left to `rand`, as it is for `SmallRng`
+impl SeedableRng for Xoshiro256PlusPlus {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        let mut s = [0; 4];
+        for (word, bytes) in s.iter_mut().zip(seed.chunks(8)) {
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Self::from_state(s).unwrap_or_else(splitmix_zero)
+    }
+}
+
+// The state `rand` swaps in for an all-zero seed: SplitMix64 seeded with 0
+fn splitmix_zero() -> Xoshiro256PlusPlus {
+    const PHI: u64 = 0x9e3779b97f4a7c15;
+    let mut state = 0u64;
+    let mut s = [0; 4];
+    for word in s.iter_mut() {
+        state = state.wrapping_add(PHI);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        *word = z ^ (z >> 31);
+    }
+    Xoshiro256PlusPlus { s }
+}
+
+impl RngCore for Xoshiro256PlusPlus {
+    // The upper bits, since the lowest ones are weaker
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+// Save states. A snapshot holds everything a running program can change:
+// memory, registers, stack, screen, timers, keys and pending waits, plus the
+// state of the random number generator. Settings such as quirks, speed and
+// palette are not included, so a snapshot should be restored into a machine
+// configured the same way.
+//
+// The format is a fixed header followed by big-endian fields in the order
+// they appear in `Snapshot`.
+use std::convert::TryInto;
+
+use crate::rng::Xoshiro256PlusPlus;
+use crate::timing::UNITS_PER_FRAME;
+use crate::{
+    Chip8Error, DisplayRow, FRAMES_PER_SECOND, FRAME_BUF_HEIGHT, MEMORY_STACK_ENTRIES, MEM_MAX,
+    REG_MAX,
+};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u8 = 2;
+
+pub struct Snapshot {
+    pub memory: [u8; MEM_MAX],
+    pub reg: [u8; REG_MAX],
+    pub index_reg: u16,
+    pub program_counter: usize,
+    pub stack: Vec<usize>,
+    pub stack_pointer: usize,
+    pub display_rows: [DisplayRow; FRAME_BUF_HEIGHT],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub is_beeping: bool,
+    pub keys: u16,
+    pub is_waiting_for_key: bool,
+    pub reg_index_key_waiting: usize,
+    pub is_waiting_for_vblank: bool,
+    pub is_rom_loaded: bool,
+    pub instruction_carry: u32,
+    pub time_budget: u64,
+    pub frame_position: u64,
+    pub error: Option<Chip8Error>,
+    pub rng: Xoshiro256PlusPlus,
+}
+
+impl Snapshot {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MEM_MAX + 512);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.reg);
+        out.extend_from_slice(&self.index_reg.to_be_bytes());
+        out.extend_from_slice(&(self.program_counter as u16).to_be_bytes());
+        out.extend_from_slice(&(self.stack.len() as u16).to_be_bytes());
+        for &address in &self.stack {
+            out.extend_from_slice(&(address as u16).to_be_bytes());
+        }
+        out.push(self.stack_pointer as u8);
+        for row in &self.display_rows {
+            out.extend_from_slice(&row.to_be_bytes());
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.push(self.is_beeping as u8);
+        out.extend_from_slice(&self.keys.to_be_bytes());
+        out.push(self.is_waiting_for_key as u8);
+        out.push(self.reg_index_key_waiting as u8);
+        out.push(self.is_waiting_for_vblank as u8);
+        out.push(self.is_rom_loaded as u8);
+        out.extend_from_slice(&self.instruction_carry.to_be_bytes());
+        out.extend_from_slice(&self.time_budget.to_be_bytes());
+        out.extend_from_slice(&self.frame_position.to_be_bytes());
+        let (kind, value) = encode_error(self.error);
+        out.push(kind);
+        out.extend_from_slice(&value.to_be_bytes());
+        for word in self.rng.state() {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    // None if `bytes` is not a snapshot this version can read
+    pub fn decode(bytes: &[u8]) -> Option<Snapshot> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC || reader.u8()? != VERSION {
+            return None;
+        }
+        let mut memory = [0; MEM_MAX];
+        memory.copy_from_slice(reader.take(MEM_MAX)?);
+        let mut reg = [0; REG_MAX];
+        reg.copy_from_slice(reader.take(REG_MAX)?);
+        let index_reg = reader.u16()?;
+        let program_counter = reader.u16()? as usize;
+        let stack_len = reader.u16()? as usize;
+        let stack = (0..stack_len)
+            .map(|_| reader.u16().map(|address| address as usize))
+            .collect::<Option<Vec<_>>>()?;
+        let stack_pointer = reader.u8()? as usize;
+        let mut display_rows = [0; FRAME_BUF_HEIGHT];
+        for row in display_rows.iter_mut() {
+            *row = reader.u64()?;
+        }
+        let snapshot = Snapshot {
+            memory,
+            reg,
+            index_reg,
+            program_counter,
+            stack,
+            stack_pointer,
+            display_rows,
+            delay_timer: reader.u8()?,
+            sound_timer: reader.u8()?,
+            is_beeping: reader.bool()?,
+            keys: reader.u16()?,
+            is_waiting_for_key: reader.bool()?,
+            reg_index_key_waiting: reader.u8()? as usize,
+            is_waiting_for_vblank: reader.bool()?,
+            is_rom_loaded: reader.bool()?,
+            instruction_carry: reader.u32()?,
+            time_budget: reader.u64()?,
+            frame_position: reader.u64()?,
+            error: decode_error(reader.u8()?, reader.u16()?)?,
+            rng: Xoshiro256PlusPlus::from_state([
+                reader.u64()?,
+                reader.u64()?,
+                reader.u64()?,
+                reader.u64()?,
+            ])?,
+        };
+        let valid = reader.bytes.is_empty()
+            && snapshot.program_counter < MEM_MAX
+            && snapshot.reg_index_key_waiting < REG_MAX
+            && snapshot.stack.iter().all(|&address| address < MEM_MAX)
+            && snapshot.stack_pointer <= MEMORY_STACK_ENTRIES
+            // Each frame leaves less than one instruction's share behind
+            && snapshot.instruction_carry < FRAMES_PER_SECOND
+            && snapshot.frame_position <= UNITS_PER_FRAME;
+        // Leftover time is always less than a frame, and more would run
+        // frames the state never asked for
+        let time_budget = snapshot.time_budget.min(UNITS_PER_FRAME - 1);
+        valid.then_some(Snapshot {
+            time_budget,
+            ..snapshot
+        })
+    }
+}
+
+fn encode_error(error: Option<Chip8Error>) -> (u8, u16) {
+    match error {
+        None => (0, 0),
+        Some(Chip8Error::UnhandledOpcode(opcode)) => (1, opcode),
+        Some(Chip8Error::StackUnderflow) => (2, 0),
+        Some(Chip8Error::StackOverflow) => (3, 0),
+        Some(Chip8Error::MemoryOutOfBounds { address }) => (4, address as u16),
+        Some(Chip8Error::WriteProtected { address }) => (5, address as u16),
+        Some(Chip8Error::ProgramCounterOutOfBounds { address }) => (6, address as u16),
+    }
+}
+
+// The outer None is a corrupt snapshot, the inner one a machine without a fault
+fn decode_error(kind: u8, value: u16) -> Option<Option<Chip8Error>> {
+    let address = value as usize;
+    Some(match kind {
+        0 => None,
+        1 => Some(Chip8Error::UnhandledOpcode(value)),
+        2 => Some(Chip8Error::StackUnderflow),
+        3 => Some(Chip8Error::StackOverflow),
+        4 => Some(Chip8Error::MemoryOutOfBounds { address }),
+        5 => Some(Chip8Error::WriteProtected { address }),
+        6 => Some(Chip8Error::ProgramCounterOutOfBounds { address }),
+        _ => return None,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
@@ -378,6 +378,12 @@ fn test_display_sprite_collision() {
     assert_eq!(chip8.frame_buffer[3 * 64 + 3], 0);  // (3, 3)
 }
 
+// Lights a pixel behind the interpreter's back, as if drawn earlier
+fn light_pixel(chip8: &mut Chip8, x: usize, y: usize) {
+    chip8.display_rows[y] |= 1 << (FRAME_BUF_WIDTH - 1 - x);
+    chip8.unpack_row(y);
+}
+
 #[test]
 fn test_display_sprite_partial_collision() {
     let mut chip8 = Chip8::new();
@@ -387,8 +393,8 @@ fn test_display_sprite_partial_collision() {
     chip8.memory[0x200] = 0xF0; // Binary: 11110000
     
     // Pre-populate screen with some pixels exactly where the sprite will draw
-    chip8.frame_buffer[2 * 64 + 2] = 1;  // Set pixel at (2, 2) - first sprite bit
-    chip8.frame_buffer[2 * 64 + 4] = 1;  // Set pixel at (4, 2) - third sprite bit  
+    light_pixel(&mut chip8, 2, 2);  // Set pixel at (2, 2) - first sprite bit
+    light_pixel(&mut chip8, 4, 2);  // Set pixel at (4, 2) - third sprite bit  
     
     println!("Before draw - existing pixels:");
     for y in 0..10 {
@@ -426,9 +432,9 @@ fn test_display_sprite_no_collision_with_zeros() {
     
     // Pre-populate screen with pixels where sprite has 0 bits
     // Based on previous tests, sprite draws vertically from (2,2) to (2,9)
-    chip8.frame_buffer[3 * 64 + 2] = 1;  // Set pixel at (2, 3) - sprite bit 6 is 0
-    chip8.frame_buffer[5 * 64 + 2] = 1;  // Set pixel at (2, 5) - sprite bit 4 is 0
-    chip8.frame_buffer[9 * 64 + 2] = 1;  // Set pixel at (2, 9) - sprite bit 0 is 0
+    light_pixel(&mut chip8, 2, 3);  // Set pixel at (2, 3) - sprite bit 6 is 0
+    light_pixel(&mut chip8, 2, 5);  // Set pixel at (2, 5) - sprite bit 4 is 0
+    light_pixel(&mut chip8, 2, 9);  // Set pixel at (2, 9) - sprite bit 0 is 0
     
     println!("Before draw - existing pixels:");
     for y in 0..15 {
@@ -456,3 +462,1409 @@ fn test_display_sprite_no_collision_with_zeros() {
     // (sprite 0 bits don't change existing pixels, and sprite 1 bits only turn on new pixels)
     assert_eq!(chip8.reg[0xF], 0);
 }
+
+#[test]
+fn test_run_frame_decrements_timers_once() {
+    let mut chip8 = Chip8::new();
+    // 0x200: JP 0x200 (spin in place)
+    chip8.load_rom(&[0x12, 0x00]);
+    chip8.delay_timer = 10;
+    chip8.sound_timer = 3;
+
+    chip8.run_frame();
+
+    assert_eq!(chip8.delay_timer, 9);
+    assert_eq!(chip8.sound_timer, 2);
+}
+
+#[test]
+fn test_recorder_merges_identical_frames() {
+    let mut chip8 = Chip8::new();
+    let mut recorder = Recorder::new();
+
+    recorder.capture(&chip8);
+    recorder.capture(&chip8);
+    chip8.frame_buffer[0] = 1;
+    recorder.capture(&chip8);
+
+    assert_eq!(recorder.frame_count(), 2);
+    assert_eq!(recorder.duration_frames(), 3);
+}
+
+#[test]
+fn test_recorder_encodes_gif_and_apng() {
+    let mut chip8 = Chip8::new();
+    let mut recorder = Recorder::new();
+    for i in 0..4 {
+        chip8.frame_buffer[i] = 1;
+        recorder.capture(&chip8);
+    }
+
+    let gif = recorder.try_encode_gif(2).unwrap();
+    assert_eq!(&gif[0..6], b"GIF89a");
+
+    let apng = recorder.try_encode_apng(2).unwrap();
+    assert_eq!(&apng[1..4], b"PNG");
+    // Animated PNGs carry an acTL chunk before the image data
+    assert!(apng.windows(4).any(|chunk| chunk == b"acTL"));
+}
+
+#[test]
+fn test_recorder_clamps_the_scale() {
+    let mut recorder = Recorder::new();
+    recorder.push_frame(&[1; FRAME_BUF_MAX]).unwrap();
+    for (scale, expected) in [(0, 1), (usize::MAX, upscale::MAX_SCALE)] {
+        let gif = recorder.try_encode_gif(scale).unwrap();
+        let decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+        assert_eq!(decoder.width() as usize, FRAME_BUF_WIDTH * expected);
+        assert_eq!(decoder.height() as usize, FRAME_BUF_HEIGHT * expected);
+
+        let apng = recorder.try_encode_apng(scale).unwrap();
+        let reader = png::Decoder::new(&apng[..]).read_info().unwrap();
+        assert_eq!(reader.info().width as usize, FRAME_BUF_WIDTH * expected);
+    }
+}
+
+#[test]
+fn test_recorder_gif_delays_play_at_speed() {
+    let mut recorder = Recorder::new();
+    // A sprite flickering every frame for one second
+    let mut pixels = [0; FRAME_BUF_MAX];
+    for _ in 0..FRAMES_PER_SECOND {
+        pixels[0] ^= 1;
+        recorder.push_frame(&pixels).unwrap();
+    }
+
+    let gif = recorder.try_encode_gif(1).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    let mut total = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert!(frame.delay >= 2);
+        total += frame.delay as u32;
+    }
+    assert_eq!(total, 100);
+}
+
+#[test]
+fn test_recorder_rejects_frames_of_the_wrong_size() {
+    let mut recorder = Recorder::new();
+    assert_eq!(
+        recorder.push_frame(&[0; 10]),
+        Err(FrameSizeError { len: 10 })
+    );
+    assert_eq!(recorder.frame_count(), 0);
+}
+
+#[test]
+fn test_wav_recorder_beep_is_frame_accurate() {
+    let mut chip8 = Chip8::new();
+    // LD V0, 5; LD ST, V0; JP 0x204
+    chip8.load_rom(&[0x60, 0x05, 0xF0, 0x18, 0x12, 0x04]);
+    let mut wav = WavRecorder::new();
+
+    for _ in 0..10 {
+        chip8.run_frame();
+        wav.capture(&chip8);
+    }
+
+    // 44100Hz / 60Hz = 735 samples per frame
+    assert_eq!(wav.sample_count(), 10 * 735);
+    assert_eq!(wav.beep_frames(), 5);
+    assert!(wav.samples()[..5 * 735].iter().all(|&s| s != 0));
+    assert!(wav.samples()[5 * 735..].iter().all(|&s| s == 0));
+
+    let bytes = wav.encode_wav();
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(&bytes[8..12], b"WAVE");
+    assert_eq!(bytes.len(), 44 + 10 * 735 * 2);
+}
+
+#[test]
+fn test_quirk_vf_reset() {
+    let mut chip8 = Chip8::new();
+    chip8.reg[0xF] = 1;
+    chip8.handle_opcode(0x8121); // V1 |= V2
+    assert_eq!(chip8.reg[0xF], 1);
+
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.handle_opcode(0x8121);
+    assert_eq!(chip8.reg[0xF], 0);
+}
+
+#[test]
+fn test_quirk_shift_uses_vy() {
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.reg[1] = 0xFF;
+    chip8.reg[2] = 0b0000_0110;
+
+    chip8.handle_opcode(0x8126); // V1 = V2 >> 1
+    assert_eq!(chip8.reg[1], 0b0000_0011);
+    assert_eq!(chip8.reg[0xF], 0);
+}
+
+#[test]
+fn test_quirk_jump_uses_vx() {
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(Quirks::SCHIP);
+    chip8.reg[0] = 0x10;
+    chip8.reg[3] = 0x02;
+
+    chip8.handle_opcode(0xB300); // PC = 0x300 + V3
+    assert_eq!(chip8.program_counter, 0x302);
+}
+
+#[test]
+fn test_quirk_memory_increment() {
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(Quirks::SCHIP);
+    chip8.index_reg = 0x300;
+
+    chip8.handle_opcode(0xF255); // Store V0..V2
+    assert_eq!(chip8.index_reg, 0x300);
+}
+
+#[test]
+fn test_quirk_clipping() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0x300;
+    chip8.memory[0x300] = 0xFF;
+    chip8.reg[0] = 60;
+    chip8.reg[1] = 0;
+
+    // Wrapping: the last four pixels land on the left edge
+    chip8.handle_opcode(0xD011);
+    assert_eq!(chip8.frame_buffer[0], 1);
+
+    chip8.handle_opcode(0x00E0);
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.handle_opcode(0xD011);
+    assert_eq!(chip8.frame_buffer[0], 0);
+    assert_eq!(chip8.frame_buffer[63], 1);
+}
+
+#[test]
+fn test_quirk_profiles_round_trip() {
+    for &(name, quirks) in QUIRK_PROFILES {
+        assert_eq!(Quirks::from_profile(name), Some(quirks));
+        assert_eq!(quirks.profile_name().as_deref(), Some(name));
+    }
+    assert_eq!(Quirks::from_profile("nonsense"), None);
+}
+
+#[test]
+fn test_platforms_name_the_profile_they_guess() {
+    for platform in [Platform::Chip8, Platform::SuperChip, Platform::XoChip] {
+        assert_eq!(
+            Quirks::from_profile(platform.profile_name()),
+            Some(platform.quirks())
+        );
+    }
+}
+
+// (fixed bits mask, fixed bits) for every instruction the reference models;
+// the remaining bits are filled with random operands
+const OPCODE_TEMPLATES: &[(u16, u16)] = &[
+    (0xFFFF, 0x00E0),
+    (0xFFFF, 0x00EE),
+    (0xF000, 0x1000),
+    (0xF000, 0x2000),
+    (0xF000, 0x3000),
+    (0xF000, 0x4000),
+    (0xF00F, 0x5000),
+    (0xF000, 0x6000),
+    (0xF000, 0x7000),
+    (0xF00F, 0x8000),
+    (0xF00F, 0x8001),
+    (0xF00F, 0x8002),
+    (0xF00F, 0x8003),
+    (0xF00F, 0x8004),
+    (0xF00F, 0x8005),
+    (0xF00F, 0x8006),
+    (0xF00F, 0x8007),
+    (0xF00F, 0x800E),
+    (0xF00F, 0x9000),
+    (0xF000, 0xA000),
+    (0xF000, 0xB000),
+    (0xF000, 0xC000),
+    (0xF000, 0xD000),
+    (0xF0FF, 0xE09E),
+    (0xF0FF, 0xE0A1),
+    (0xF0FF, 0xF007),
+    (0xF0FF, 0xF015),
+    (0xF0FF, 0xF018),
+    (0xF0FF, 0xF01E),
+    (0xF0FF, 0xF029),
+    (0xF0FF, 0xF033),
+    (0xF0FF, 0xF055),
+    (0xF0FF, 0xF065),
+];
+
+fn opcode_strategy() -> impl proptest::strategy::Strategy<Value = u16> {
+    use proptest::prelude::*;
+    (0..OPCODE_TEMPLATES.len(), any::<u16>()).prop_map(|(template, operands)| {
+        let (mask, bits) = OPCODE_TEMPLATES[template];
+        bits | (operands & !mask)
+    })
+}
+
+proptest::proptest! {
+    #[test]
+    fn test_interpreter_matches_reference_model(
+        profile in 0..QUIRK_PROFILES.len(),
+        seed in proptest::prelude::any::<u64>(),
+        keys in proptest::prelude::any::<u16>(),
+        registers in proptest::prelude::any::<[u8; 16]>(),
+        opcodes in proptest::collection::vec(opcode_strategy(), 1..64),
+    ) {
+        let quirks = QUIRK_PROFILES[profile].1;
+        let mut chip8 = Chip8::with_seed(seed);
+        chip8.set_quirks(quirks);
+        chip8.keys = keys;
+        chip8.reg = registers;
+        // Give sprite and load instructions something other than zeros to read
+        for (i, byte) in chip8.memory[START_OF_ROM..].iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(0x9D);
+        }
+
+        let mut expected = reference::State::from_chip8(&chip8);
+        for opcode in opcodes {
+            if !expected.can_execute(opcode) {
+                continue;
+            }
+            let random = chip8.rand_rng.clone().gen::<u8>();
+            expected = reference::step(&expected, opcode, quirks, random);
+            chip8.handle_opcode(opcode);
+
+            let actual = reference::State::from_chip8(&chip8);
+            proptest::prop_assert_eq!(&actual.reg, &expected.reg, "V registers after {:04X}", opcode);
+            proptest::prop_assert_eq!(actual.index_reg, expected.index_reg, "I after {:04X}", opcode);
+            proptest::prop_assert_eq!(&actual.stack, &expected.stack, "stack after {:04X}", opcode);
+            proptest::prop_assert_eq!(actual.program_counter, expected.program_counter, "PC after {:04X}", opcode);
+            proptest::prop_assert!(actual.memory == expected.memory, "memory after {:04X}", opcode);
+            proptest::prop_assert!(actual.frame_buffer == expected.frame_buffer, "frame buffer after {:04X}", opcode);
+            proptest::prop_assert_eq!(&actual, &expected);
+        }
+    }
+}
+
+#[test]
+fn test_return_with_empty_stack_faults() {
+    let mut chip8 = Chip8::new();
+    chip8.handle_opcode(0x00EE);
+    assert_eq!(chip8.error(), Some(Chip8Error::StackUnderflow));
+}
+
+#[test]
+fn test_store_registers_past_end_of_memory_faults() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0xFFE;
+    chip8.reg[2] = 0xAB;
+
+    chip8.handle_opcode(0xF255);
+
+    assert_eq!(
+        chip8.error(),
+        Some(Chip8Error::MemoryOutOfBounds { address: 0x1000 })
+    );
+    assert_eq!(chip8.memory[0xFFE], 0);
+}
+
+#[test]
+fn test_sprite_past_end_of_memory_faults() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0xFFC;
+    chip8.handle_opcode(0xD00F);
+    assert!(matches!(
+        chip8.error(),
+        Some(Chip8Error::MemoryOutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn test_fault_halts_execution_until_reset() {
+    let mut chip8 = Chip8::new();
+    // 0x200: unknown 0x0123 opcode, 0x202: LD V0, 1
+    chip8.load_rom(&[0x01, 0x23, 0x60, 0x01]);
+
+    chip8.tick();
+    chip8.tick();
+
+    assert_eq!(chip8.error(), Some(Chip8Error::UnhandledOpcode(0x0123)));
+    assert_eq!(chip8.program_counter, 0x202);
+    assert_eq!(chip8.reg[0], 0);
+
+    chip8.reset();
+    assert_eq!(chip8.error(), None);
+}
+
+#[test]
+fn test_running_off_end_of_memory_faults() {
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&[0x1F, 0xFF]); // JP 0xFFF
+    chip8.tick();
+    chip8.tick();
+    assert_eq!(
+        chip8.error(),
+        Some(Chip8Error::ProgramCounterOutOfBounds { address: 0xFFF })
+    );
+}
+
+#[test]
+fn test_random_roms_never_panic() {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(0x5EED);
+    for _ in 0..200 {
+        let mut rom = vec![0u8; rng.gen_range(0..=MAX_ROM_SIZE)];
+        rng.fill(&mut rom[..]);
+
+        let mut chip8 = Chip8::with_seed(rng.gen());
+        chip8.set_quirks(QUIRK_PROFILES[rng.gen_range(0..QUIRK_PROFILES.len())].1);
+        chip8.load_rom(&rom);
+        for frame in 0..30u8 {
+            chip8.set_key(frame);
+            chip8.run_frame();
+            chip8.unset_key(frame);
+        }
+    }
+}
+
+#[test]
+fn test_analysis_builds_control_flow_graph() {
+    use analysis::{ByteKind, ControlFlowGraph, EdgeKind};
+
+    let rom = [
+        0xA2, 0x0C, // 200: LD I, 0x20C
+        0x22, 0x0A, // 202: CALL 0x20A
+        0x30, 0x01, // 204: SE V0, 0x01
+        0xD0, 0x15, // 206: DRW V0, V1, 5
+        0x12, 0x08, // 208: JP 0x208
+        0x00, 0xEE, // 20A: RET
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 20C: sprite
+    ];
+    let cfg = ControlFlowGraph::build(&rom);
+
+    let starts: Vec<usize> = cfg.blocks.keys().copied().collect();
+    assert_eq!(starts, vec![0x200, 0x204, 0x206, 0x208, 0x20A]);
+    assert!(cfg.subroutines.contains(&0x20A));
+    assert!(cfg.blocks[&0x20A].returns);
+
+    let skip_edges: Vec<EdgeKind> = cfg.blocks[&0x204]
+        .successors
+        .iter()
+        .map(|edge| edge.kind)
+        .collect();
+    assert_eq!(skip_edges, vec![EdgeKind::Fallthrough, EdgeKind::Skip]);
+
+    assert!(cfg.is_code(0x208));
+    assert_eq!(cfg.byte_kind(0x20C), Some(ByteKind::Sprite));
+    assert_eq!(cfg.byte_kind(0x210), Some(ByteKind::Sprite));
+
+    let dot = cfg.to_dot();
+    assert!(dot.starts_with("digraph"));
+    assert!(dot.contains("b200 -> b20A [label=\"call\""));
+}
+
+#[test]
+fn test_analysis_reports_computed_jumps() {
+    let rom = [0x60, 0x02, 0xB2, 0x08, 0x12, 0x04, 0x12, 0x06];
+    let cfg = analysis::ControlFlowGraph::build(&rom);
+
+    assert_eq!(cfg.unresolved_jumps, vec![0x202]);
+    // Nothing after the Bnnn is reachable statically
+    assert!(!cfg.is_code(0x204));
+    assert!(cfg.to_dot().contains("style=dotted"));
+}
+
+#[test]
+fn test_analysis_keys_read_follows_constants() {
+    let rom = [
+        0x60, 0x03, // 200: LD V0, 0x03
+        0x22, 0x0C, // 202: CALL 0x20C
+        0x81, 0x00, // 204: LD V1, V0
+        0xE1, 0xA1, // 206: SKNP V1
+        0x12, 0x00, // 208: JP 0x200
+        0x12, 0x00, // 20A: JP 0x200
+        0x70, 0x01, // 20C: ADD V0, 0x01
+        0x00, 0xEE, // 20E: RET
+    ];
+    assert_eq!(analysis::ControlFlowGraph::build(&rom).keys_read(), 1 << 4);
+
+    // A key test alongside Fx0A narrows it down
+    let rom = [0x60, 0x05, 0xE0, 0x9E, 0xF1, 0x0A, 0x12, 0x00];
+    assert_eq!(analysis::ControlFlowGraph::build(&rom).keys_read(), 1 << 5);
+}
+
+#[test]
+fn test_analysis_keys_read_falls_back_to_every_key() {
+    let random = [0xC1, 0x0F, 0xE1, 0x9E, 0x12, 0x02];
+    // V0 counts up around the loop
+    let counter = [0x60, 0x00, 0xE0, 0x9E, 0x70, 0x01, 0x12, 0x02];
+    let any_key = [0xF0, 0x0A, 0x12, 0x00];
+    let computed = [0x60, 0x04, 0xE0, 0x9E, 0xB2, 0x00];
+    for rom in [&random[..], &counter, &any_key, &computed] {
+        assert_eq!(analysis::ControlFlowGraph::build(rom).keys_read(), 0xFFFF);
+    }
+}
+
+#[test]
+fn test_disassemble_mnemonics() {
+    assert_eq!(analysis::disassemble(0x00E0), "CLS");
+    assert_eq!(analysis::disassemble(0xD125), "DRW V1, V2, 5");
+    assert_eq!(analysis::disassemble(0xF265), "LD V2, [I]");
+    assert_eq!(analysis::disassemble(0x8AB4), "ADD VA, VB");
+    assert_eq!(analysis::disassemble(0x5121), "DW 0x5121");
+}
+
+#[test]
+fn test_detect_platform_from_opcodes() {
+    let plain = [0x60, 0x01, 0x12, 0x02];
+    let guess = detect_platform(&plain);
+    assert_eq!(guess.platform, Platform::Chip8);
+    assert_eq!(guess.quirks, Quirks::DEFAULT);
+
+    let schip = [0x00, 0xFF, 0x12, 0x02];
+    let guess = detect_platform(&schip);
+    assert_eq!(guess.platform, Platform::SuperChip);
+    assert_eq!(guess.quirks, Quirks::SCHIP);
+    assert!(guess.confidence > 0.8);
+
+    let xo_chip = [0xF0, 0x00, 0x02, 0x06, 0x12, 0x04];
+    let guess = detect_platform(&xo_chip);
+    assert_eq!(guess.platform, Platform::XoChip);
+    assert_eq!(guess.quirks, Quirks::XO_CHIP);
+
+    assert_eq!(detect_platform(&[]).confidence, 0.0);
+}
+
+#[test]
+fn test_detect_platform_ignores_unreachable_opcodes() {
+    // 00FF sits behind an unconditional jump, so it is data rather than code
+    let rom = [0x12, 0x04, 0x00, 0xFF, 0x12, 0x04];
+    assert_eq!(detect_platform(&rom).platform, Platform::Chip8);
+}
+
+#[test]
+fn test_detect_platform_quirk_patterns() {
+    // 8016: shifts V1 into V0, which only means that on the VIP
+    let rom = [0x80, 0x16, 0x12, 0x02];
+    let guess = detect_platform(&rom);
+    assert!(guess.quirks.shift_uses_vy);
+    assert!(guess.confidence < 0.6);
+
+    // Two Fx65 reads in a row walk a table, which needs I to advance
+    let rom = [0x00, 0xFF, 0xA2, 0x08, 0xF1, 0x65, 0xF1, 0x65, 0x12, 0x08];
+    let guess = detect_platform(&rom);
+    assert_eq!(guess.platform, Platform::SuperChip);
+    assert!(guess.quirks.memory_increment);
+}
+
+#[test]
+fn test_load_rom_applies_detected_quirks() {
+    let schip = [0x00, 0xFF, 0x12, 0x02];
+
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&schip);
+    assert_eq!(chip8.get_quirks(), Quirks::DEFAULT);
+    assert!(chip8.get_platform_guess().is_none());
+
+    chip8.reset();
+    chip8.set_auto_detect(true);
+    chip8.load_rom(&schip);
+    assert_eq!(chip8.get_quirks(), Quirks::SCHIP);
+    assert_eq!(
+        chip8.get_platform_guess().map(|guess| guess.platform),
+        Some(Platform::SuperChip)
+    );
+
+    chip8.reset();
+    assert!(chip8.get_platform_guess().is_none());
+}
+
+#[test]
+fn test_analysis_long_load_is_one_instruction() {
+    let rom = [
+        0x30, 0x00, // 200: SE V0, 0x00
+        0xF0, 0x00, 0x12, 0x00, // 202: LD I, long 0x1200
+        0x12, 0x06, // 206: JP 0x206
+    ];
+    let cfg = analysis::ControlFlowGraph::build(&rom);
+
+    assert!(cfg.blocks.contains_key(&0x206));
+    assert!(!cfg.blocks.contains_key(&0x204));
+    assert!(cfg.disassembly(&rom).contains("LD I, long 0x1200"));
+}
+
+#[test]
+fn test_rom_database_is_sorted_by_hash() {
+    let entries = metadata::ROM_DATABASE;
+    assert!(!entries.is_empty());
+    assert!(entries.windows(2).all(|pair| pair[0].sha1 < pair[1].sha1));
+}
+
+#[test]
+fn test_reward_database_is_sorted_by_hash() {
+    let entries = env::RewardTable::builtin().entries();
+    assert!(!entries.is_empty());
+    assert!(entries.windows(2).all(|pair| pair[0].sha1 < pair[1].sha1));
+    assert!(entries.iter().all(|entry| !entry.score.is_empty()));
+}
+
+#[test]
+fn test_run_frame_uses_ticks_per_frame() {
+    // 7001: ADD V0, 1, repeated
+    let rom: Vec<u8> = [0x70, 0x01].repeat(20);
+    let mut chip8 = Chip8::new();
+    chip8.set_ticks_per_frame(12);
+    chip8.load_rom(&rom);
+    chip8.run_frame();
+    assert_eq!(chip8.reg[0], 12);
+}
+
+#[test]
+fn test_run_for_runs_whole_frames_in_fixed_timing() {
+    let rom: Vec<u8> = [0x70, 0x01].repeat(100);
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&rom);
+    chip8.delay_timer = 10;
+
+    // Just short of a frame: nothing runs until the rest arrives
+    chip8.run_for(16_666);
+    assert_eq!(chip8.reg[0], 0);
+    chip8.run_for(1);
+    assert_eq!(chip8.reg[0], 7);
+    assert_eq!(chip8.delay_timer, 9);
+
+    chip8.run_for(2 * 16_667);
+    assert_eq!(chip8.reg[0], 21);
+    assert_eq!(chip8.delay_timer, 7);
+}
+
+#[test]
+fn test_instructions_per_second_carry_between_frames() {
+    let rom: Vec<u8> = [0x70, 0x01].repeat(100);
+    let mut chip8 = Chip8::new();
+    chip8.set_instructions_per_second(90);
+    chip8.load_rom(&rom);
+
+    chip8.run_frame();
+    assert_eq!(chip8.reg[0], 1);
+    chip8.run_frame();
+    assert_eq!(chip8.reg[0], 3);
+    assert_eq!(chip8.get_ticks_per_frame(), 1);
+}
+
+#[test]
+fn test_extreme_speeds_are_capped() {
+    let mut chip8 = Chip8::new();
+    chip8.set_ticks_per_frame(usize::MAX);
+    assert_eq!(chip8.get_instructions_per_second(), timing::MAX_INSTRUCTIONS_PER_SECOND);
+    chip8.set_instructions_per_second(u32::MAX);
+    assert_eq!(chip8.get_instructions_per_second(), timing::MAX_INSTRUCTIONS_PER_SECOND);
+
+    // 1200: JP 0x200, forever
+    chip8.load_rom(&[0x12, 0x00]);
+    chip8.run_frame();
+    chip8.run_frame();
+    assert_eq!(chip8.program_counter, 0x200);
+    assert!(chip8.error.is_none());
+}
+
+#[test]
+fn test_vip_timing_charges_each_instruction() {
+    // 200: ADD V0, 1 (45us); 202: JP 0x200 (105us)
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let mut chip8 = Chip8::new();
+    chip8.set_timing_mode(TimingMode::CosmacVip);
+    chip8.load_rom(&rom);
+
+    chip8.run_frame();
+    // 1/60s fits 111 full loops
+    assert_eq!(chip8.reg[0], 111);
+
+    // 27us is not enough for the next ADD but is kept for the next call
+    let mut chip8 = Chip8::new();
+    chip8.set_timing_mode(TimingMode::CosmacVip);
+    chip8.load_rom(&rom);
+    chip8.run_for(27);
+    assert_eq!(chip8.reg[0], 0);
+    chip8.run_for(18);
+    assert_eq!(chip8.reg[0], 1);
+}
+
+#[test]
+fn test_vip_timing_waits_for_vblank_after_drawing() {
+    // 200: DRW V0, V0, 1; 202: ADD V1, 1; 204: JP 0x200
+    let rom = [0xD0, 0x01, 0x71, 0x01, 0x12, 0x00];
+    let mut chip8 = Chip8::new();
+    chip8.set_timing_mode(TimingMode::CosmacVip);
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.load_rom(&rom);
+    chip8.delay_timer = 10;
+
+    for _ in 0..3 {
+        chip8.run_frame();
+    }
+    // One sprite per frame, so the loop only went round twice
+    assert_eq!(chip8.reg[1], 2);
+    assert_eq!(chip8.delay_timer, 7);
+
+    // Without the display wait quirk the loop keeps going
+    let mut chip8 = Chip8::new();
+    chip8.set_timing_mode(TimingMode::CosmacVip);
+    chip8.load_rom(&rom);
+    chip8.run_frame();
+    // Each loop costs 102 + 45 + 105us
+    assert_eq!(chip8.reg[1], 66);
+}
+
+#[test]
+fn test_quirk_display_wait() {
+    // 200: DRW V0, V0, 1; 202: ADD V1, 1; 204: JP 0x200
+    let rom = [0xD0, 0x01, 0x71, 0x01, 0x12, 0x00];
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.load_rom(&rom);
+
+    // The rest of each 7-instruction frame is spent waiting after the draw
+    chip8.run_frame();
+    assert_eq!(chip8.reg[1], 0);
+    assert_eq!(chip8.program_counter, 0x202);
+    chip8.run_frame();
+    assert_eq!(chip8.reg[1], 1);
+    assert_eq!(chip8.program_counter, 0x202);
+
+    // Turning the quirk off releases the wait
+    chip8.set_quirks(Quirks::DEFAULT);
+    assert!(!chip8.is_waiting_for_vblank);
+}
+
+#[test]
+fn test_quirk_display_wait_with_single_steps() {
+    // 200: DRW V0, V0, 1; 202: ADD V1, 1; 204: JP 0x200
+    let rom = [0xD0, 0x01, 0x71, 0x01, 0x12, 0x00];
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(Quirks::COSMAC_VIP);
+    chip8.load_rom(&rom);
+
+    // Every tick ends a frame, so a draw never holds up the next tick
+    for _ in 0..8 {
+        chip8.tick();
+    }
+    assert_eq!(chip8.reg[1], 3);
+    assert_eq!(chip8.program_counter, 0x204);
+}
+
+#[test]
+fn test_stack_overflow_faults() {
+    // 200: CALL 0x200, recursing forever
+    let rom = [0x22, 0x00];
+    let mut chip8 = Chip8::new();
+    chip8.set_stack_limit(Some(12));
+    chip8.load_rom(&rom);
+    for _ in 0..20 {
+        chip8.tick();
+    }
+    assert_eq!(chip8.error(), Some(Chip8Error::StackOverflow));
+    assert_eq!(chip8.stack.len(), 12);
+
+    let mut chip8 = Chip8::new();
+    chip8.set_stack_limit(None);
+    chip8.load_rom(&rom);
+    for _ in 0..100 {
+        chip8.tick();
+    }
+    assert_eq!(chip8.error(), None);
+    assert_eq!(chip8.stack.len(), 100);
+}
+
+#[test]
+fn test_stack_in_memory() {
+    let mut chip8 = Chip8::new();
+    chip8.set_stack_in_memory(true);
+    chip8.program_counter = 0x234;
+    chip8.handle_opcode(0x2300);
+
+    // The return address is visible to the ROM at 0xEA0
+    assert!(chip8.stack.is_empty());
+    assert_eq!(&chip8.memory[0xEA0..0xEA2], &[0x02, 0x34]);
+
+    // ...and writing there changes where 00EE goes
+    chip8.memory[0xEA1] = 0x40;
+    chip8.handle_opcode(0x00EE);
+    assert_eq!(chip8.program_counter, 0x240);
+
+    // Only 24 entries fit, whatever the limit
+    chip8.set_stack_limit(None);
+    for _ in 0..25 {
+        chip8.handle_opcode(0x2300);
+    }
+    assert_eq!(chip8.error(), Some(Chip8Error::StackOverflow));
+}
+
+#[test]
+fn test_stack_moves_between_modes() {
+    let mut chip8 = Chip8::new();
+    chip8.stack = vec![0x202, 0x310];
+    chip8.set_stack_in_memory(true);
+    assert_eq!(&chip8.memory[0xEA0..0xEA4], &[0x02, 0x02, 0x03, 0x10]);
+
+    chip8.set_stack_in_memory(false);
+    assert_eq!(chip8.stack, vec![0x202, 0x310]);
+}
+
+#[test]
+fn test_deep_stack_stays_out_of_memory() {
+    // 200-230: each instruction calls the next; 232: JP 0x232
+    let mut rom = Vec::new();
+    for i in 0..25u16 {
+        rom.extend_from_slice(&(0x2000 | (0x202 + i * 2)).to_be_bytes());
+    }
+    rom.extend_from_slice(&[0x12, 0x32]);
+    let mut chip8 = Chip8::new();
+    chip8.set_stack_limit(None);
+    chip8.load_rom(&rom);
+    for _ in 0..25 {
+        chip8.tick();
+    }
+    let pending: Vec<usize> = (0..25).map(|i| 0x202 + i * 2).collect();
+    assert_eq!(chip8.stack, pending);
+
+    // 25 returns do not fit in the 24 slots, so the switch is refused
+    assert!(!chip8.set_stack_in_memory(true));
+    assert!(!chip8.is_stack_in_memory());
+    assert_eq!(chip8.stack, pending);
+
+    // Once one has returned, the rest move over with the newest on top
+    chip8.handle_opcode(0x00EE);
+    assert_eq!(chip8.program_counter, 0x232);
+    assert!(chip8.set_stack_in_memory(true));
+    chip8.handle_opcode(0x00EE);
+    assert_eq!(chip8.program_counter, 0x230);
+}
+
+#[test]
+fn test_write_protection() {
+    let mut chip8 = Chip8::new();
+    chip8.reg[0] = 0xAA;
+    chip8.index_reg = 0x50;
+    chip8.handle_opcode(0xF055);
+    assert_eq!(chip8.memory[0x50], 0xAA);
+    assert_eq!(chip8.error(), None);
+
+    // With protection on the font survives and the write is reported
+    let mut chip8 = Chip8::new();
+    chip8.set_write_protection(true);
+    chip8.reg[0] = 0xAA;
+    chip8.index_reg = 0x50;
+    chip8.handle_opcode(0xF055);
+    assert_eq!(chip8.memory[0x50], 0xF0);
+    assert_eq!(chip8.index_reg, 0x50);
+    assert_eq!(
+        chip8.error(),
+        Some(Chip8Error::WriteProtected { address: 0x50 })
+    );
+
+    // Program memory is still writable
+    let mut chip8 = Chip8::new();
+    chip8.set_write_protection(true);
+    chip8.reg[0] = 123;
+    chip8.index_reg = 0x300;
+    chip8.handle_opcode(0xF033);
+    assert_eq!(&chip8.memory[0x300..0x303], &[1, 2, 3]);
+    assert_eq!(chip8.error(), None);
+}
+
+#[test]
+fn test_vip_layout_maps_display() {
+    let mut chip8 = Chip8::new();
+    chip8.set_vip_layout(true);
+
+    // Drawing "0" at (8, 1) shows up in the second byte of the second row
+    chip8.reg[0] = 8;
+    chip8.reg[1] = 1;
+    chip8.index_reg = 0x50;
+    chip8.handle_opcode(0xD015);
+    assert_eq!(chip8.memory[0xF09], 0xF0);
+    assert_eq!(chip8.memory[0xF11], 0x90);
+
+    // Writing to the display area draws
+    chip8.reg[0] = 0x81;
+    chip8.index_reg = 0xF00;
+    chip8.handle_opcode(0xF055);
+    assert_eq!(chip8.frame_buffer[0], 1);
+    assert_eq!(chip8.frame_buffer[7], 1);
+    assert_eq!(chip8.frame_buffer[1..7], [0; 6]);
+
+    chip8.handle_opcode(0x00E0);
+    assert_eq!(chip8.memory[0xF00..0x1000], [0; 256]);
+
+    // ROMs have to end before the interpreter's variables
+    let mut chip8 = Chip8::new();
+    chip8.set_vip_layout(true);
+    chip8.load_rom(&[0; 0xCA1]);
+    assert!(!chip8.is_rom_loaded);
+    chip8.load_rom(&[0; 0xCA0]);
+    assert!(chip8.is_rom_loaded);
+}
+
+#[test]
+fn test_vip_clear_reaches_cached_code() {
+    for engine in [ExecutionEngine::CachedDecode, ExecutionEngine::BlockJit] {
+        // 200: CLS; 202: JP 0xF00
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x00, 0xE0, 0x1F, 0x00]);
+        chip8.set_vip_layout(true);
+        chip8.set_execution_engine(engine);
+        // F00: LD V0, 5; F02: JP 0x200, drawn into the top display row
+        chip8.write_memory(0xF00, &[0x60, 0x05, 0x12, 0x00]);
+
+        chip8.program_counter = 0xF00;
+        chip8.run_frame();
+        assert_eq!(chip8.reg[0], 5);
+
+        // Once cleared, 0xF00 holds 0000, which is not an instruction
+        chip8.reg[0] = 0;
+        chip8.program_counter = 0x200;
+        chip8.run_frame();
+        assert_eq!(chip8.error(), Some(Chip8Error::UnhandledOpcode(0)));
+        assert_eq!(chip8.reg[0], 0);
+    }
+}
+
+#[test]
+fn test_memory_regions_cover_memory() {
+    for &(vip_layout, stack_in_memory) in
+        &[(false, false), (false, true), (true, false), (true, true)]
+    {
+        let mut chip8 = Chip8::new();
+        chip8.set_vip_layout(vip_layout);
+        chip8.set_stack_in_memory(stack_in_memory);
+        chip8.set_write_protection(true);
+
+        let regions = chip8.memory_regions();
+        assert_eq!(regions[0].start, 0);
+        assert_eq!(regions.last().unwrap().end, MEM_MAX);
+        for pair in regions.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        assert_eq!(regions.len(), chip8.region_count());
+    }
+
+    let mut chip8 = Chip8::new();
+    chip8.set_vip_layout(true);
+    chip8.set_write_protection(true);
+    let font = chip8.region_at(0x60).unwrap();
+    assert_eq!(font.kind, RegionKind::Font);
+    assert!(font.protected);
+    assert_eq!(chip8.region_at(0xF80).unwrap().kind, RegionKind::Display);
+    assert!(!chip8.region_at(0x200).unwrap().protected);
+    assert_eq!(chip8.region_at(MEM_MAX), None);
+}
+
+#[test]
+fn test_dirty_region_tracking() {
+    let mut chip8 = Chip8::new();
+    assert_eq!(chip8.take_dirty_region(), None);
+
+    // "0" at (10, 4) and "1" at (20, 6) merge into one box
+    chip8.reg[0] = 10;
+    chip8.reg[1] = 4;
+    chip8.index_reg = 0x50;
+    chip8.handle_opcode(0xD015);
+    chip8.reg[0] = 20;
+    chip8.reg[1] = 6;
+    chip8.index_reg = 0x55;
+    chip8.handle_opcode(0xD015);
+    assert_eq!(
+        chip8.take_dirty_region(),
+        Some(DirtyRegion {
+            x: 10,
+            y: 4,
+            width: 14,
+            height: 7,
+        })
+    );
+    assert_eq!(chip8.take_dirty_region(), None);
+
+    // A sprite row of zeros changes nothing
+    chip8.memory[0x300] = 0;
+    chip8.index_reg = 0x300;
+    chip8.handle_opcode(0xD011);
+    assert_eq!(chip8.take_dirty_region(), None);
+
+    chip8.handle_opcode(0x00E0);
+    assert_eq!(chip8.take_dirty_region(), Some(DirtyRegion::FULL_SCREEN));
+}
+
+#[test]
+fn test_draws_signal_once_per_frame() {
+    // 200: LD I, 0x50; 202: DRW V0, V0, 5; 204: JP 0x202
+    let rom = [0xA0, 0x50, 0xD0, 0x05, 0x12, 0x02];
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&rom);
+    for _ in 0..7 {
+        chip8.tick();
+    }
+    assert!(chip8.frame_changed);
+    chip8.run_frame();
+    chip8.run_frame();
+    // Each frame drew, and each end cleared the flag after signaling
+    assert!(!chip8.frame_changed);
+    assert_eq!(
+        chip8.take_dirty_region(),
+        Some(DirtyRegion {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 5,
+        })
+    );
+}
+
+#[test]
+fn test_rgba_screen_uses_palette() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0x50;
+    chip8.handle_opcode(0xD001);
+
+    // "0" starts with four lit pixels
+    let rgba = chip8.rgba_screen();
+    assert_eq!(rgba.len(), FRAME_BUF_MAX * 4);
+    assert_eq!(&rgba[0..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(&rgba[16..20], &[0x00, 0x00, 0x00, 0xFF]);
+
+    chip8.set_palette(BuiltinPalette::GameBoy);
+    let rgba = chip8.rgba_screen();
+    assert_eq!(&rgba[0..4], &[0x0F, 0x38, 0x0F, 0xFF]);
+    assert_eq!(&rgba[16..20], &[0x9B, 0xBC, 0x0F, 0xFF]);
+
+    // Later draws show up without touching the palette
+    chip8.handle_opcode(0x00E0);
+    assert_eq!(&chip8.rgba_screen()[0..4], &[0x9B, 0xBC, 0x0F, 0xFF]);
+
+    assert!(!chip8.set_custom_palette(&[0x123456]));
+    assert!(chip8.set_custom_palette(&[0x123456, 0xFFABCDEF]));
+    assert_eq!(chip8.get_palette(), vec![0x123456, 0xABCDEF]);
+    assert_eq!(&chip8.rgba_screen()[0..4], &[0x12, 0x34, 0x56, 0xFF]);
+}
+
+#[test]
+fn test_phosphor_filter_fades() {
+    // 200: LD I, 0x50; 202: DRW V0, V0, 1; 204: JP 0x204
+    let rom = [0xA0, 0x50, 0xD0, 0x01, 0x12, 0x04];
+    let mut chip8 = Chip8::new();
+    chip8.set_flicker_filter(FlickerFilter::Phosphor);
+    chip8.set_phosphor_decay(0.5);
+    chip8.load_rom(&rom);
+    chip8.run_frame();
+    assert_eq!(chip8.filtered_screen()[0], 0xFF);
+
+    // Erase the row; it fades by half each frame instead of vanishing
+    chip8.handle_opcode(0xD001);
+    chip8.run_frame();
+    assert_eq!(chip8.filtered_screen()[0], 0x7F);
+    assert_eq!(&chip8.rgba_screen()[0..4], &[0x7F, 0x7F, 0x7F, 0xFF]);
+    chip8.run_frame();
+    assert_eq!(chip8.filtered_screen()[0], 0x3F);
+    for _ in 0..8 {
+        chip8.run_frame();
+    }
+    assert_eq!(chip8.filtered_screen()[0], 0);
+}
+
+#[test]
+fn test_merge_last_two_filter() {
+    let rom = [0xA0, 0x50, 0xD0, 0x01, 0x12, 0x04];
+    let mut chip8 = Chip8::new();
+    chip8.set_flicker_filter(FlickerFilter::MergeLastTwo);
+    chip8.load_rom(&rom);
+    chip8.run_frame();
+
+    // Erased for one frame, the sprite stays lit
+    chip8.handle_opcode(0xD001);
+    chip8.run_frame();
+    assert_eq!(chip8.filtered_screen()[0], 0xFF);
+    assert_eq!(&chip8.rgba_screen()[0..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    chip8.run_frame();
+    assert_eq!(chip8.filtered_screen()[0], 0);
+
+    // Turning the filter off shows the raw frame buffer again
+    chip8.handle_opcode(0xD001);
+    chip8.set_flicker_filter(FlickerFilter::Off);
+    assert_eq!(&chip8.rgba_screen()[0..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(&chip8.rgba_screen()[16..20], &[0x00, 0x00, 0x00, 0xFF]);
+}
+
+#[test]
+fn test_upscalers() {
+    let mut chip8 = Chip8::new();
+    // A diagonal pair of pixels: (0, 0) and (1, 1)
+    light_pixel(&mut chip8, 0, 0);
+    light_pixel(&mut chip8, 1, 1);
+    chip8.mark_dirty(DirtyRegion::FULL_SCREEN);
+    let lit = |screen: &[u8], width: usize, x: usize, y: usize| screen[(y * width + x) * 4] != 0;
+
+    chip8.set_upscaler(Upscaler::Nearest, 3);
+    let width = chip8.get_upscaled_width();
+    assert_eq!(width, 192);
+    let screen = chip8.upscaled_screen().to_vec();
+    assert_eq!(screen.len(), 192 * 96 * 4);
+    assert!(lit(&screen, width, 2, 2));
+    assert!(!lit(&screen, width, 3, 2));
+
+    // Scale2x turns the staircase into a smooth diagonal, filling in the
+    // gaps and trimming the outer corners
+    chip8.set_upscaler(Upscaler::Epx, 2);
+    let screen = chip8.upscaled_screen().to_vec();
+    assert!(lit(&screen, 128, 0, 0));
+    assert!(lit(&screen, 128, 2, 1));
+    assert!(lit(&screen, 128, 1, 2));
+    assert!(!lit(&screen, 128, 1, 1));
+    assert!(!lit(&screen, 128, 3, 0));
+
+    // The smoothed corners are blends rather than full copies
+    chip8.set_upscaler(Upscaler::Hqx, 2);
+    let screen = chip8.upscaled_screen().to_vec();
+    assert_eq!(screen[(128 + 2) * 4], 0x7F);
+
+    // Scanlines darken the bottom row of each pixel
+    chip8.set_upscaler(Upscaler::Crt, 2);
+    let screen = chip8.upscaled_screen().to_vec();
+    assert_eq!(&screen[0..4], &[0xFF, 0xB3, 0xB3, 0xFF]);
+    assert_eq!(&screen[128 * 4..128 * 4 + 4], &[0x7F, 0x59, 0x59, 0xFF]);
+
+    // Changes to the screen are picked up
+    chip8.handle_opcode(0x00E0);
+    assert!(chip8.upscaled_screen().chunks(4).all(|p| p[0] == 0));
+}
+
+#[test]
+fn test_frame_buffer_unpacks_when_control_returns() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0x50;
+
+    // Mid-frame, a draw only marks the rows it changed
+    chip8.dispatch(0xD015);
+    assert_eq!(chip8.stale_rows, 0b11111);
+    assert!(chip8.frame_buffer.iter().all(|&pixel| pixel == 0));
+
+    // The host sees them unpacked
+    chip8.handle_opcode(0xD015);
+    chip8.handle_opcode(0xD015);
+    assert_eq!(chip8.stale_rows, 0);
+    assert_eq!(&chip8.frame_buffer[..4], &[1, 1, 1, 1]);
+
+    // 200: LD I, 0x50; 202: DRW V0, V0, 5; 204: JP 0x204
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&[0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04]);
+    chip8.run_frame();
+    assert_eq!(chip8.stale_rows, 0);
+    assert_eq!(&chip8.frame_buffer[..4], &[1, 1, 1, 1]);
+}
+
+#[test]
+fn test_packed_rows_match_screen() {
+    let mut chip8 = Chip8::new();
+    chip8.index_reg = 0x50;
+    // "0" straddling the right edge wraps its last column to x = 0
+    chip8.reg[0] = 61;
+    chip8.handle_opcode(0xD015);
+    assert_eq!(chip8.display_rows()[0], 0x8000_0000_0000_0007);
+    assert_eq!(chip8.display_rows()[1], 0x8000_0000_0000_0004);
+    for (y, &row) in chip8.display_rows().iter().enumerate() {
+        for x in 0..FRAME_BUF_WIDTH {
+            let bit = (row >> (FRAME_BUF_WIDTH - 1 - x)) as u8 & 1;
+            assert_eq!(chip8.frame_buffer()[y * FRAME_BUF_WIDTH + x], bit);
+        }
+    }
+
+    // Drawn again, only the lit pixels collide
+    chip8.handle_opcode(0xD015);
+    assert_eq!(chip8.reg[0xF], 1);
+    assert!(chip8.display_rows().iter().all(|&row| row == 0));
+    assert!(chip8.frame_buffer().iter().all(|&pixel| pixel == 0));
+}
+
+#[test]
+fn test_cached_decode_sees_self_modifying_code() {
+    // 200: JP 0x20C
+    // 20C: LD V2, 0x01, rewritten below to LD V2, 0x07
+    // 20E: LD V0, 0x62; 210: LD V1, 0x07; 212: LD I, 0x20C; 214: LD [I], V1
+    // 216: JP 0x20C
+    let mut rom = vec![0x12, 0x0C];
+    rom.resize(0x0C, 0);
+    rom.extend_from_slice(&[
+        0x62, 0x01, 0x60, 0x62, 0x61, 0x07, 0xA2, 0x0C, 0xF1, 0x55, 0x12, 0x0C,
+    ]);
+
+    for engine in [ExecutionEngine::Interpreter, ExecutionEngine::CachedDecode] {
+        let mut chip8 = Chip8::new();
+        chip8.set_execution_engine(engine);
+        chip8.load_rom(&rom);
+        chip8.tick();
+        chip8.tick();
+        assert_eq!(chip8.reg[2], 0x01);
+
+        // The second visit to 20C runs the rewritten instruction
+        for _ in 0..6 {
+            chip8.tick();
+        }
+        assert_eq!(chip8.program_counter, 0x20E);
+        assert_eq!(chip8.reg[2], 0x07, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_block_jit_matches_interpreter_on_self_modifying_code() {
+    // Same loop as above: the block compiled at 20C ends on LD [I], V1, which
+    // rewrites the block's own first instruction
+    let mut rom = vec![0x12, 0x0C];
+    rom.resize(0x0C, 0);
+    rom.extend_from_slice(&[
+        0x62, 0x01, 0x60, 0x62, 0x61, 0x07, 0xA2, 0x0C, 0xF1, 0x55, 0x12, 0x0C,
+    ]);
+
+    let run = |engine| {
+        let mut chip8 = Chip8::new();
+        chip8.set_execution_engine(engine);
+        chip8.load_rom(&rom);
+        for _ in 0..3 {
+            chip8.run_frame();
+        }
+        chip8
+    };
+    let expected = run(ExecutionEngine::Interpreter);
+    let actual = run(ExecutionEngine::BlockJit);
+    assert_eq!(actual.reg[2], 0x07);
+    assert_eq!(actual.reg, expected.reg);
+    assert_eq!(actual.index_reg, expected.index_reg);
+    assert_eq!(actual.program_counter, expected.program_counter);
+    assert!(actual.memory() == expected.memory());
+}
+
+#[test]
+fn test_block_jit_leaves_computed_jumps_to_interpreter() {
+    // 200: LD V0, 0x04; 202: JP V0, 0x206 (to 20A); 204: JP 0x204
+    // 20A: ADD V1, 0x01; 20C: SE V1, 0x05; 20E: JP 0x200
+    // 210: JP 0x210
+    let mut rom = vec![0x60, 0x04, 0xB2, 0x06, 0x12, 0x04];
+    rom.resize(0x0A, 0);
+    rom.extend_from_slice(&[0x71, 0x01, 0x31, 0x05, 0x12, 0x00, 0x12, 0x10]);
+
+    let mut chip8 = Chip8::new();
+    chip8.set_execution_engine(ExecutionEngine::BlockJit);
+    chip8.load_rom(&rom);
+    for _ in 0..10 {
+        chip8.run_frame();
+    }
+    assert_eq!(chip8.error(), None);
+    assert_eq!(chip8.reg[1], 5);
+    assert_eq!(chip8.program_counter, 0x210);
+}
+
+#[test]
+fn test_wasm_blocks_take_only_quirk_free_instructions() {
+    for opcode in [0x6012, 0x7FFF, 0xA123, 0x8120, 0x8124, 0x8125, 0x8127, 0xF31E, 0xF329] {
+        assert!(wasm_block::compiles(opcode));
+    }
+    // Vf reset, shifts, random numbers, timers and anything that branches
+    for opcode in [0x8121, 0x8126, 0x812E, 0xC0FF, 0xF007, 0xF015, 0x1200, 0xD015] {
+        assert!(!wasm_block::compiles(opcode));
+    }
+
+    // (func (param i32 i32) (local i32) (i32.store8 offset=1 (local.get 0) (i32.const 5)))
+    let module = wasm_block::encode(&[&[0x6105]]);
+    let mut expected = b"\0asm\x01\0\0\0".to_vec();
+    expected.extend_from_slice(&[0x01, 0x06, 0x01, 0x60, 0x02, 0x7F, 0x7F, 0x00]);
+    expected.extend_from_slice(&[0x02, 0x0F, 0x01, 0x03]);
+    expected.extend_from_slice(b"env\x06memory\x02\x00\x00");
+    expected.extend_from_slice(&[0x03, 0x02, 0x01, 0x00]);
+    expected.extend_from_slice(&[0x07, 0x06, 0x01, 0x02, b'r', b'0', 0x00, 0x00]);
+    expected.extend_from_slice(&[0x0A, 0x0D, 0x01, 0x0B, 0x01, 0x01, 0x7F]);
+    expected.extend_from_slice(&[0x20, 0x00, 0x41, 0x05, 0x3A, 0x00, 0x01, 0x0B]);
+    assert_eq!(module, expected);
+
+    // Constants are signed LEB128, so 0xFF takes two bytes
+    let module = wasm_block::encode(&[&[0x60FF]]);
+    assert!(module.windows(3).any(|bytes| bytes == [0x41, 0xFF, 0x01]));
+}
+
+// Draws random sprites forever, calling a subroutine between draws:
+// 202: RND V0, 0x3F; 204: RND V1, 0x1F; 206: DRW V0, V1, 5; 208: CALL 0x210
+// 20A: JP 0x202; 210: ADD V2, 0x01; 212: RET
+fn random_sprites_rom() -> Vec<u8> {
+    let mut rom = vec![
+        0xA0, 0x50, 0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x15, 0x22, 0x10, 0x12, 0x02,
+    ];
+    rom.resize(0x10, 0);
+    rom.extend_from_slice(&[0x72, 0x01, 0x00, 0xEE]);
+    rom
+}
+
+fn run_frames(chip8: &mut Chip8, frames: usize) {
+    for _ in 0..frames {
+        chip8.run_frame();
+    }
+}
+
+#[test]
+fn test_load_state_replays_from_the_snapshot() {
+    let rom = random_sprites_rom();
+    let mut chip8 = Chip8::with_seed(7);
+    chip8.load_rom(&rom);
+    run_frames(&mut chip8, 5);
+    let state = chip8.save_state();
+    run_frames(&mut chip8, 5);
+    let rows = chip8.display_rows;
+    let reg = chip8.reg;
+    let pc = chip8.program_counter;
+
+    // Back in the same machine and in a new one with another seed, the same
+    // frames draw the same random sprites
+    let mut other = Chip8::with_seed(99);
+    other.load_rom(&rom);
+    for machine in [&mut chip8, &mut other] {
+        assert!(machine.load_state(&state));
+        run_frames(machine, 5);
+        assert_eq!(machine.display_rows, rows);
+        assert_eq!(machine.reg, reg);
+        assert_eq!(machine.program_counter, pc);
+    }
+    assert!(other.frame_buffer() == chip8.frame_buffer());
+    assert!(other.memory() == chip8.memory());
+}
+
+#[test]
+fn test_load_state_rejects_bad_snapshots() {
+    let mut chip8 = Chip8::with_seed(7);
+    chip8.load_rom(&random_sprites_rom());
+    run_frames(&mut chip8, 2);
+    let state = chip8.save_state();
+    let pc = chip8.program_counter;
+
+    let mut bad_version = state.clone();
+    bad_version[4] += 1;
+    let mut trailing = state.clone();
+    trailing.push(0);
+    // `is_rom_loaded`, counted back from the end past the generator, the
+    // error and the timing fields
+    let mut bad_flag = state.clone();
+    let last_flag = state.len() - 32 - 2 - 1 - 8 - 8 - 4 - 1;
+    bad_flag[last_flag] = 2;
+    for bad in [&[][..], &state[..state.len() - 1], &bad_version, &trailing, &bad_flag] {
+        let mut target = Chip8::new();
+        assert!(!target.load_state(bad));
+        assert_eq!(target.program_counter, START_OF_ROM);
+        assert!(!target.is_rom_loaded);
+    }
+    assert!(chip8.load_state(&state));
+    assert_eq!(chip8.program_counter, pc);
+}
+
+#[test]
+fn test_load_state_rejects_impossible_timing() {
+    let mut chip8 = Chip8::with_seed(7);
+    chip8.load_rom(&random_sprites_rom());
+    run_frames(&mut chip8, 2);
+    let state = chip8.save_state();
+
+    // Counted back from the end past the generator and the error
+    let frame_position = state.len() - 32 - 3 - 8;
+    let time_budget = frame_position - 8;
+    let instruction_carry = time_budget - 4;
+    let with = |offset: usize, bytes: &[u8]| {
+        let mut state = state.clone();
+        state[offset..offset + bytes.len()].copy_from_slice(bytes);
+        state
+    };
+
+    let past_frame = with(frame_position, &(UNITS_PER_FRAME + 1).to_be_bytes());
+    let carry = with(instruction_carry, &FRAMES_PER_SECOND.to_be_bytes());
+    let no_rng = with(state.len() - 32, &[0; 32]);
+    for bad in [&past_frame, &carry, &no_rng] {
+        let mut target = Chip8::new();
+        assert!(!target.load_state(bad));
+    }
+
+    // A budget of many frames is cut to what one call can leave behind
+    let mut target = Chip8::new();
+    assert!(target.load_state(&with(time_budget, &u64::MAX.to_be_bytes())));
+    assert_eq!(target.time_budget, UNITS_PER_FRAME - 1);
+    target.run_for(0);
+    assert_eq!(target.time_budget, UNITS_PER_FRAME - 1);
+}
+
+// Seeds keep drawing the numbers they drew with `SmallRng`
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn test_rng_matches_small_rng() {
+    use rand::RngCore;
+    assert_eq!(
+        Xoshiro256PlusPlus::from_seed([0; 32]).next_u64(),
+        rand::rngs::SmallRng::from_seed([0; 32]).next_u64()
+    );
+    for seed in [0, 7, 0x5EED, u64::MAX] {
+        let mut ours = Xoshiro256PlusPlus::seed_from_u64(seed);
+        let mut theirs = rand::rngs::SmallRng::seed_from_u64(seed);
+        for _ in 0..100 {
+            assert_eq!(ours.gen::<u8>(), theirs.gen::<u8>());
+            assert_eq!(ours.next_u64(), theirs.next_u64());
+        }
+    }
+}
+
+#[test]
+fn test_save_state_leaves_the_machine_alone() {
+    let rom = random_sprites_rom();
+    let mut saved = Chip8::with_seed(7);
+    let mut untouched = Chip8::with_seed(7);
+    for chip8 in [&mut saved, &mut untouched] {
+        chip8.load_rom(&rom);
+        run_frames(chip8, 3);
+    }
+    let state = saved.save_state();
+    assert_eq!(saved.save_state(), state);
+    for chip8 in [&mut saved, &mut untouched] {
+        run_frames(chip8, 5);
+    }
+    assert_eq!(saved.display_rows, untouched.display_rows);
+    assert_eq!(saved.reg, untouched.reg);
+}
+
+#[test]
+fn test_write_memory_reaches_cached_code() {
+    // 200: LD V0, 0x01 ; 202: JP 0x200
+    let rom = [0x60, 0x01, 0x12, 0x00];
+    for engine in [
+        ExecutionEngine::Interpreter,
+        ExecutionEngine::CachedDecode,
+        ExecutionEngine::BlockJit,
+    ] {
+        let mut chip8 = Chip8::new();
+        chip8.set_execution_engine(engine);
+        chip8.load_rom(&rom);
+        run_frames(&mut chip8, 2);
+        assert_eq!(chip8.registers()[0], 1);
+
+        assert!(chip8.write_memory(0x201, &[0x07]));
+        run_frames(&mut chip8, 2);
+        assert_eq!(chip8.registers()[0], 7, "{:?}", engine);
+    }
+
+    let mut chip8 = Chip8::new();
+    assert!(!chip8.write_memory(MEM_MAX - 1, &[0, 0]));
+    assert!(chip8.write_memory(MEM_MAX - 2, &[0xAB, 0xCD]));
+    assert_eq!(&chip8.memory()[MEM_MAX - 2..], &[0xAB, 0xCD]);
+}
+
+#[test]
+fn test_register_accessors() {
+    let mut chip8 = Chip8::new();
+    chip8.handle_opcode(0xA123);
+    chip8.set_register(0xF, 9);
+    chip8.set_register(REG_MAX, 1);
+    assert_eq!(chip8.registers()[0xF], 9);
+    assert_eq!(chip8.registers().len(), REG_MAX);
+    assert_eq!(chip8.index_register(), 0x123);
+    assert_eq!(chip8.program_counter(), START_OF_ROM);
+}
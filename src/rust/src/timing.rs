@@ -0,0 +1,56 @@
+// How emulated time advances. `Fixed` runs a set number of instructions per
+// second with the timers ticking once per 60Hz frame. `CosmacVip` charges each
+// instruction what it took on the original interpreter, so a frame holds as
+// many instructions as fit in 1/60s.
+use wasm_bindgen::prelude::*;
+
+use crate::get_x;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingMode {
+    Fixed,
+    CosmacVip,
+}
+
+// Time is counted in 1/60 microseconds so a 60Hz frame is a whole number
+pub const UNITS_PER_MICRO: u64 = 60;
+pub const UNITS_PER_FRAME: u64 = 1_000_000;
+
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 420;
+// A million instructions a frame. Faster settings are lowered to this, which
+// also keeps the per-frame instruction count from overflowing.
+pub const MAX_INSTRUCTIONS_PER_SECOND: u32 = 60_000_000;
+
+// Approximate time in microseconds the VIP interpreter spends on `opcode`.
+// Sprite draws grow with height and cost more when the sprite straddles two
+// bytes of display memory. BCD is done by repeated subtraction, so it takes
+// longer for larger digits, and register transfers grow with the count.
+pub fn vip_cost_micros(opcode: u16, vx: u8) -> u64 {
+    let x = get_x(opcode) as u64;
+    match opcode & 0xF000 {
+        0x0000 if opcode == 0x00E0 => 109,
+        0x0000 | 0x1000 | 0x2000 | 0xB000 => 105,
+        0x3000 | 0x4000 => 55,
+        0x5000 | 0x9000 => 73,
+        0x6000 => 27,
+        0x7000 => 45,
+        0x8000 => 200,
+        0xA000 => 55,
+        0xC000 => 164,
+        0xD000 => {
+            let rows = (opcode & 0xF) as u64;
+            let per_row = if vx.is_multiple_of(8) { 34 } else { 68 };
+            68 + rows * per_row
+        }
+        0xE000 => 73,
+        _ => match opcode & 0xFF {
+            0x07 | 0x0A | 0x15 | 0x18 => 45,
+            0x1E => 86,
+            0x29 => 91,
+            0x33 => 364 + (vx as u64 / 100 + vx as u64 / 10 % 10 + vx as u64 % 10) * 73,
+            0x55 | 0x65 => 64 + (x + 1) * 64,
+            _ => 45,
+        },
+    }
+}
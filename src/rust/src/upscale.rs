@@ -0,0 +1,243 @@
+// Pixel-art upscalers for the RGBA screen. Each takes RGBA8 rows and returns
+// a new buffer `scale` times wider and taller.
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upscaler {
+    Nearest,
+    // AdvanceMAME Scale2x/Scale3x (EPX), chained for larger factors
+    Epx,
+    // hq2x-style smoothing: edges are found by comparing neighbours in YUV
+    // like hq2x, but blended with a few fixed weights instead of its full
+    // 256-case table
+    Hqx,
+    // Nearest with dark scanline gaps and an RGB shadow mask
+    Crt,
+}
+
+pub const MAX_SCALE: usize = 16;
+
+// How much of each channel survives in a scanline gap and under the two
+// mask stripes that are not its own, in 1/256ths
+const SCANLINE_LEVEL: u32 = 128;
+const MASK_LEVEL: u32 = 180;
+
+// Colors as 0xRRGGBBAA for easy comparison
+struct Image {
+    pixels: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    fn from_rgba(rgba: &[u8], width: usize, height: usize) -> Self {
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|p| u32::from_be_bytes([p[0], p[1], p[2], p[3]]))
+            .collect();
+        Image {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    fn into_rgba(self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| p.to_be_bytes()).collect()
+    }
+
+    fn blank(width: usize, height: usize) -> Self {
+        Image {
+            pixels: vec![0; width * height],
+            width,
+            height,
+        }
+    }
+
+    // Neighbours past the edge repeat the edge
+    fn at(&self, x: isize, y: isize) -> u32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // The 3x3 neighbourhood of (x, y), row by row
+    fn neighbourhood(&self, x: usize, y: usize) -> [u32; 9] {
+        let (x, y) = (x as isize, y as isize);
+        let mut out = [0; 9];
+        for (i, pixel) in out.iter_mut().enumerate() {
+            *pixel = self.at(x + i as isize % 3 - 1, y + i as isize / 3 - 1);
+        }
+        out
+    }
+
+    // Calls `block` for every source pixel with its neighbourhood and the
+    // `factor` x `factor` output block, row by row
+    fn map_blocks(&self, factor: usize, block: impl Fn(&[u32; 9], &mut [u32])) -> Image {
+        let mut out = Image::blank(self.width * factor, self.height * factor);
+        let mut cells = vec![0; factor * factor];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                block(&self.neighbourhood(x, y), &mut cells);
+                for (row, cell_row) in cells.chunks(factor).enumerate() {
+                    let start = (y * factor + row) * out.width + x * factor;
+                    out.pixels[start..start + factor].copy_from_slice(cell_row);
+                }
+            }
+        }
+        out
+    }
+}
+
+// Returns `scale` x `scale` output pixels per input pixel. `scale` is
+// clamped to 1..=MAX_SCALE.
+pub fn upscale(
+    upscaler: Upscaler,
+    scale: usize,
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    let mut image = Image::from_rgba(rgba, width, height);
+    let mut remaining = scale;
+    match upscaler {
+        Upscaler::Nearest | Upscaler::Crt => {}
+        Upscaler::Epx => {
+            while remaining.is_multiple_of(3) {
+                image = image.map_blocks(3, scale3x);
+                remaining /= 3;
+            }
+            while remaining.is_multiple_of(2) {
+                image = image.map_blocks(2, scale2x);
+                remaining /= 2;
+            }
+        }
+        Upscaler::Hqx => {
+            while remaining.is_multiple_of(2) {
+                image = image.map_blocks(2, hq2x);
+                remaining /= 2;
+            }
+        }
+    }
+    // Whatever the smarter filters could not cover
+    if remaining > 1 {
+        image = image.map_blocks(remaining, |n, cells| cells.fill(n[4]));
+    }
+    if upscaler == Upscaler::Crt {
+        apply_crt(&mut image, scale);
+    }
+    image.into_rgba()
+}
+
+fn scale2x(n: &[u32; 9], out: &mut [u32]) {
+    let [_, b, _, d, e, f, _, h, _] = *n;
+    let flat = b == h || d == f;
+    out[0] = if !flat && d == b { d } else { e };
+    out[1] = if !flat && b == f { f } else { e };
+    out[2] = if !flat && d == h { d } else { e };
+    out[3] = if !flat && h == f { f } else { e };
+}
+
+fn scale3x(n: &[u32; 9], out: &mut [u32]) {
+    let [a, b, c, d, e, f, g, h, i] = *n;
+    out.fill(e);
+    if b == h || d == f {
+        return;
+    }
+    if d == b {
+        out[0] = d;
+    }
+    if (d == b && e != c) || (b == f && e != a) {
+        out[1] = b;
+    }
+    if b == f {
+        out[2] = f;
+    }
+    if (d == b && e != g) || (d == h && e != a) {
+        out[3] = d;
+    }
+    if (b == f && e != i) || (h == f && e != c) {
+        out[5] = f;
+    }
+    if d == h {
+        out[6] = d;
+    }
+    if (d == h && e != i) || (h == f && e != g) {
+        out[7] = h;
+    }
+    if h == f {
+        out[8] = f;
+    }
+}
+
+fn hq2x(n: &[u32; 9], out: &mut [u32]) {
+    let e = n[4];
+    // Each corner with its two edge neighbours
+    let corners = [(n[1], n[3]), (n[1], n[5]), (n[7], n[3]), (n[7], n[5])];
+    for (cell, &(edge1, edge2)) in out.iter_mut().zip(&corners) {
+        *cell = if similar(edge1, edge2) && !similar(e, edge1) {
+            blend(&[(e, 2), (edge1, 1), (edge2, 1)])
+        } else {
+            e
+        };
+    }
+}
+
+// hq2x's test: close in brightness and in both chroma components
+fn similar(a: u32, b: u32) -> bool {
+    let (ya, ua, va) = yuv(a);
+    let (yb, ub, vb) = yuv(b);
+    (ya - yb).abs() <= 48 && (ua - ub).abs() <= 7 && (va - vb).abs() <= 6
+}
+
+fn yuv(color: u32) -> (i32, i32, i32) {
+    let [r, g, b, _] = color.to_be_bytes();
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    (
+        (r + g + b) >> 2,
+        128 + ((r - b) >> 2),
+        128 + ((2 * g - r - b) >> 3),
+    )
+}
+
+// Weighted average of each channel
+fn blend(colors: &[(u32, u32)]) -> u32 {
+    let total: u32 = colors.iter().map(|&(_, weight)| weight).sum();
+    let mut channels = [0u8; 4];
+    for (i, channel) in channels.iter_mut().enumerate() {
+        let sum: u32 = colors
+            .iter()
+            .map(|&(color, weight)| color.to_be_bytes()[i] as u32 * weight)
+            .sum();
+        *channel = (sum / total) as u8;
+    }
+    u32::from_be_bytes(channels)
+}
+
+// Darkens the last row of every source pixel and stripes columns red,
+// green and blue. Needs a scale of at least 2 to leave anything lit.
+fn apply_crt(image: &mut Image, scale: usize) {
+    if scale < 2 {
+        return;
+    }
+    for y in 0..image.height {
+        let scanline = y % scale == scale - 1;
+        for x in 0..image.width {
+            let pixel = &mut image.pixels[y * image.width + x];
+            let mut channels = pixel.to_be_bytes();
+            for (i, channel) in channels[..3].iter_mut().enumerate() {
+                let mut level = 256;
+                if i != x % 3 {
+                    level = level * MASK_LEVEL / 256;
+                }
+                if scanline {
+                    level = level * SCANLINE_LEVEL / 256;
+                }
+                *channel = (*channel as u32 * level / 256) as u8;
+            }
+            *pixel = u32::from_be_bytes(channels);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// WebAssembly code generation for `BlockJit` on the wasm target. Runs of
+// register-only instructions inside a block are translated into functions of
+// a small WebAssembly module that imports this crate's own memory, so the
+// browser compiles them to machine code and they update the registers in
+// place. Everything else in the block stays a closure.
+//
+// Only instructions whose behaviour no quirk changes are translated, so a
+// block stays correct when the quirks change under it. Each function takes
+// the addresses of `reg` and `index_reg` and returns nothing.
+
+// Shorter runs cost more to call across JavaScript than they save
+#[cfg(target_arch = "wasm32")]
+const MIN_RUN: usize = 4;
+
+// Instructions `encode` can translate
+pub fn compiles(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x6000 | 0x7000 | 0xA000 => true,
+        0x8000 => matches!(opcode & 0xF, 0x0 | 0x4 | 0x5 | 0x7),
+        0xF000 => matches!(opcode & 0xFF, 0x1E | 0x29),
+        _ => false,
+    }
+}
+
+// Local indices in every function
+const REG: u8 = 0;
+const INDEX: u8 = 1;
+const TEMP: u8 = 2;
+
+const I32: u8 = 0x7F;
+const END: u8 = 0x0B;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const I32_LOAD8_U: u8 = 0x2D;
+const I32_LOAD16_U: u8 = 0x2F;
+const I32_STORE8: u8 = 0x3A;
+const I32_STORE16: u8 = 0x3B;
+const I32_CONST: u8 = 0x41;
+const I32_GE_S: u8 = 0x4E;
+const I32_ADD: u8 = 0x6A;
+const I32_SUB: u8 = 0x6B;
+const I32_MUL: u8 = 0x6C;
+const I32_SHR_U: u8 = 0x76;
+
+fn unsigned(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, body: &[u8]) {
+    out.push(id);
+    unsigned(out, body.len() as u32);
+    out.extend_from_slice(body);
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+// One function body's instructions, writing the same registers as the
+// interpreter in the same order
+struct Body(Vec<u8>);
+
+impl Body {
+    fn get(&mut self, local: u8) -> &mut Self {
+        self.0.extend_from_slice(&[LOCAL_GET, local]);
+        self
+    }
+
+    fn set(&mut self, local: u8) -> &mut Self {
+        self.0.extend_from_slice(&[LOCAL_SET, local]);
+        self
+    }
+
+    fn constant(&mut self, value: i32) -> &mut Self {
+        self.0.push(I32_CONST);
+        signed(&mut self.0, value);
+        self
+    }
+
+    fn op(&mut self, op: u8) -> &mut Self {
+        self.0.push(op);
+        self
+    }
+
+    // Loads and stores take an alignment of 1 and the field offset
+    fn memory(&mut self, op: u8, offset: usize) -> &mut Self {
+        self.0.extend_from_slice(&[op, 0]);
+        unsigned(&mut self.0, offset as u32);
+        self
+    }
+
+    fn load_reg(&mut self, register: usize) -> &mut Self {
+        self.get(REG).memory(I32_LOAD8_U, register)
+    }
+
+    // Vx = TEMP, then VF = whether TEMP did not go below zero
+    fn store_difference(&mut self, x: usize) {
+        self.get(REG).get(TEMP).memory(I32_STORE8, x);
+        self.get(REG).get(TEMP).constant(0).op(I32_GE_S);
+        self.memory(I32_STORE8, 0xF);
+    }
+
+    fn instruction(&mut self, opcode: u16) {
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let kk = (opcode & 0xFF) as i32;
+        match opcode & 0xF000 {
+            0x6000 => {
+                self.get(REG).constant(kk).memory(I32_STORE8, x);
+            }
+            0x7000 => {
+                self.get(REG).load_reg(x).constant(kk).op(I32_ADD);
+                self.memory(I32_STORE8, x);
+            }
+            0xA000 => {
+                self.get(INDEX).constant((opcode & 0xFFF) as i32);
+                self.memory(I32_STORE16, 0);
+            }
+            0x8000 => match opcode & 0xF {
+                0x0 => {
+                    self.get(REG).load_reg(y).memory(I32_STORE8, x);
+                }
+                0x4 => {
+                    self.load_reg(x).load_reg(y).op(I32_ADD).set(TEMP);
+                    self.get(REG).get(TEMP).memory(I32_STORE8, x);
+                    self.get(REG).get(TEMP).constant(8).op(I32_SHR_U);
+                    self.memory(I32_STORE8, 0xF);
+                }
+                0x5 => {
+                    self.load_reg(x).load_reg(y).op(I32_SUB).set(TEMP);
+                    self.store_difference(x);
+                }
+                _ => {
+                    self.load_reg(y).load_reg(x).op(I32_SUB).set(TEMP);
+                    self.store_difference(x);
+                }
+            },
+            _ => {
+                self.get(INDEX);
+                if opcode & 0xFF == 0x1E {
+                    self.get(INDEX)
+                        .memory(I32_LOAD16_U, 0)
+                        .load_reg(x)
+                        .op(I32_ADD);
+                } else {
+                    self.load_reg(x).constant(5).op(I32_MUL);
+                    self.constant(crate::START_OF_FONT as i32).op(I32_ADD);
+                }
+                self.memory(I32_STORE16, 0);
+            }
+        }
+    }
+}
+
+// A module importing `env.memory` and exporting `r0`, `r1`, ... one function
+// per run. Every opcode must pass `compiles`.
+pub fn encode(runs: &[&[u16]]) -> Vec<u8> {
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+    // (i32, i32) -> ()
+    section(&mut module, 1, &[1, 0x60, 2, I32, I32, 0]);
+
+    let mut imports = vec![1];
+    name(&mut imports, "env");
+    name(&mut imports, "memory");
+    // A memory of any size
+    imports.extend_from_slice(&[0x02, 0x00, 0]);
+    section(&mut module, 2, &imports);
+
+    let mut functions = Vec::new();
+    unsigned(&mut functions, runs.len() as u32);
+    functions.extend(runs.iter().map(|_| 0));
+    section(&mut module, 3, &functions);
+
+    let mut exports = Vec::new();
+    unsigned(&mut exports, runs.len() as u32);
+    for index in 0..runs.len() {
+        name(&mut exports, &format!("r{}", index));
+        exports.push(0x00);
+        unsigned(&mut exports, index as u32);
+    }
+    section(&mut module, 7, &exports);
+
+    let mut code = Vec::new();
+    unsigned(&mut code, runs.len() as u32);
+    for run in runs {
+        // One i32 local after the two parameters
+        let mut body = Body(vec![1, 1, I32]);
+        for &opcode in run.iter() {
+            debug_assert!(compiles(opcode));
+            body.instruction(opcode);
+        }
+        body.op(END);
+        unsigned(&mut code, body.0.len() as u32);
+        code.extend_from_slice(&body.0);
+    }
+    section(&mut module, 10, &code);
+    module
+}
+
+#[cfg(target_arch = "wasm32")]
+mod runtime {
+    use js_sys::{Function, Object, Reflect, Uint8Array, WebAssembly};
+    use wasm_bindgen::{JsCast, JsValue};
+
+    use super::{compiles, encode, MIN_RUN};
+    use crate::{jit::Op, Chip8};
+
+    // The wasm build is single-threaded, so a function never leaves the
+    // thread that compiled it
+    struct Compiled(Function);
+    unsafe impl Send for Compiled {}
+    unsafe impl Sync for Compiled {}
+
+    fn instantiate(runs: &[&[u16]]) -> Result<Vec<Function>, JsValue> {
+        let bytes = Uint8Array::new_from_slice(&encode(runs));
+        let module = WebAssembly::Module::new(&bytes)?;
+        let env = Object::new();
+        Reflect::set(&env, &"memory".into(), &wasm_bindgen::memory())?;
+        let imports = Object::new();
+        Reflect::set(&imports, &"env".into(), &env)?;
+        let exports = WebAssembly::Instance::new(&module, &imports)?.exports();
+        (0..runs.len())
+            .map(|index| Reflect::get(&exports, &format!("r{}", index).into())?.dyn_into())
+            .collect()
+    }
+
+    fn call(function: Function) -> Op {
+        let function = Compiled(function);
+        Box::new(move |chip8: &mut Chip8| {
+            let reg = chip8.reg.as_mut_ptr() as u32;
+            let index = &mut chip8.index_reg as *mut u16 as u32;
+            let _ = function
+                .0
+                .call2(&JsValue::NULL, &JsValue::from(reg), &JsValue::from(index));
+        })
+    }
+
+    // Replaces each long enough run of translatable instructions with one
+    // call into a compiled function. If the browser refuses the module, the
+    // closures are kept.
+    pub fn link(straight: Vec<(u16, Op)>) -> Vec<Op> {
+        let opcodes: Vec<u16> = straight.iter().map(|&(opcode, _)| opcode).collect();
+        let mut spans = Vec::new();
+        let mut start = 0;
+        while start < opcodes.len() {
+            let len = opcodes[start..]
+                .iter()
+                .take_while(|&&opcode| compiles(opcode))
+                .count();
+            if len >= MIN_RUN {
+                spans.push(start..start + len);
+            }
+            start += len.max(1);
+        }
+        if spans.is_empty() {
+            return straight.into_iter().map(|(_, op)| op).collect();
+        }
+        let runs: Vec<&[u16]> = spans.iter().map(|span| &opcodes[span.clone()]).collect();
+        let mut functions = match instantiate(&runs) {
+            Ok(functions) => functions.into_iter(),
+            Err(_) => return straight.into_iter().map(|(_, op)| op).collect(),
+        };
+
+        let mut ops = Vec::new();
+        let mut spans = spans.into_iter().peekable();
+        for (position, (_, op)) in straight.into_iter().enumerate() {
+            match spans.peek() {
+                Some(span) if span.contains(&position) => {
+                    if position + 1 == span.end {
+                        spans.next();
+                        ops.push(call(functions.next().unwrap()));
+                    }
+                }
+                _ => ops.push(op),
+            }
+        }
+        ops
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use runtime::link;
@@ -0,0 +1,187 @@
+//! `Chip8Batch` must behave exactly like the same number of separate machines
+//! given the same seeds and keys, whether stepped on one thread or several.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{Chip8, Chip8Batch};
+
+const SEEDS: [u64; 5] = [1, 2, 3, 4, 5];
+const FRAMES: u32 = 120;
+const FRAME_BUF_HEIGHT: usize = 32;
+const ROMS: &[&str] = &["BRIX.ch8", "TANK.ch8", "BLITZ.ch8", "TETRIS.ch8"];
+
+fn load(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test_roms")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err))
+}
+
+// Each machine cycles through the keys at its own offset
+fn keys_for(machine: usize, frame: u32) -> u16 {
+    if frame % 15 < 8 {
+        1 << (((frame / 15) as usize + machine) % 16)
+    } else {
+        0
+    }
+}
+
+fn step_separate(chip8: &mut Chip8, keys: u16) {
+    for key in 0..16u8 {
+        let held = keys >> key & 1 != 0;
+        if held && chip8.get_keys() >> key & 1 == 0 {
+            chip8.set_key(key);
+        } else if !held {
+            chip8.unset_key(key);
+        }
+    }
+    chip8.run_frame();
+}
+
+fn rows_of(batch: &Chip8Batch, machine: usize) -> &[u64] {
+    &batch.display_rows()[machine * FRAME_BUF_HEIGHT..(machine + 1) * FRAME_BUF_HEIGHT]
+}
+
+#[test]
+fn batch_matches_separate_machines() {
+    for name in ROMS {
+        let rom = load(name);
+        let mut batch = Chip8Batch::new(&SEEDS);
+        batch.load_rom(&rom);
+        let mut separate: Vec<Chip8> = SEEDS
+            .iter()
+            .map(|&seed| {
+                let mut chip8 = Chip8::with_seed(seed);
+                chip8.load_rom(&rom);
+                chip8
+            })
+            .collect();
+
+        for frame in 0..FRAMES {
+            for (machine, chip8) in separate.iter_mut().enumerate() {
+                batch.set_keys(machine, keys_for(machine, frame));
+                step_separate(chip8, keys_for(machine, frame));
+            }
+            batch.step();
+        }
+        for (machine, chip8) in separate.iter().enumerate() {
+            assert_eq!(rows_of(&batch, machine), chip8.display_rows(), "{}", name);
+            assert_eq!(
+                &batch.frame_buffers()[machine * 2048..(machine + 1) * 2048],
+                chip8.frame_buffer(),
+                "{}",
+                name
+            );
+            assert_eq!(batch.is_halted(machine), Some(chip8.error().is_some()));
+            assert_eq!(batch.is_beeping(machine), Some(chip8.is_beeping()));
+            assert!(
+                &batch.memory()[machine * 4096..(machine + 1) * 4096] == chip8.memory(),
+                "{}",
+                name
+            );
+            assert_eq!(
+                &batch.registers()[machine * 16..(machine + 1) * 16],
+                chip8.registers()
+            );
+            assert_eq!(batch.index_registers()[machine], chip8.index_register());
+            assert_eq!(batch.program_counters()[machine], chip8.program_counter());
+            assert_eq!(batch.delay_timers()[machine], chip8.delay_timer());
+            assert_eq!(batch.sound_timers()[machine], chip8.sound_timer());
+        }
+    }
+}
+
+#[test]
+fn reset_starts_one_machine_over() {
+    let rom = load("BRIX.ch8");
+    let mut batch = Chip8Batch::new(&SEEDS);
+    batch.load_rom(&rom);
+    for _ in 0..FRAMES {
+        batch.step();
+    }
+    let others: Vec<Vec<u64>> = (0..SEEDS.len())
+        .map(|machine| rows_of(&batch, machine).to_vec())
+        .collect();
+
+    assert!(batch.reset(2, 42));
+    assert_eq!(batch.get_seed(2), Some(42));
+    let mut fresh = Chip8::with_seed(42);
+    fresh.load_rom(&rom);
+    assert_eq!(rows_of(&batch, 2), fresh.display_rows());
+    for (machine, rows) in others.iter().enumerate() {
+        if machine != 2 {
+            assert_eq!(rows_of(&batch, machine), &rows[..]);
+        }
+    }
+
+    for _ in 0..FRAMES {
+        batch.step();
+        fresh.run_frame();
+    }
+    assert_eq!(rows_of(&batch, 2), fresh.display_rows());
+}
+
+#[test]
+fn load_rom_starts_every_machine_over() {
+    let mut batch = Chip8Batch::new(&SEEDS);
+    batch.load_rom(&load("BRIX.ch8"));
+    for _ in 0..FRAMES {
+        batch.step();
+    }
+    let rom = load("TANK.ch8");
+    batch.load_rom(&rom);
+    let mut fresh = Chip8Batch::new(&SEEDS);
+    fresh.load_rom(&rom);
+
+    for frame in 0..=FRAMES {
+        for (machine, expected) in batch.machines().iter().zip(fresh.machines()) {
+            assert!(machine.save_state() == expected.save_state(), "frame {}", frame);
+        }
+        assert_eq!(batch.registers(), fresh.registers());
+        assert_eq!(batch.program_counters(), fresh.program_counters());
+        assert_eq!(batch.display_rows(), fresh.display_rows());
+        batch.step();
+        fresh.step();
+    }
+}
+
+#[test]
+fn threaded_step_matches_step() {
+    let rom = load("TANK.ch8");
+    let mut single = Chip8Batch::new(&SEEDS);
+    let mut threaded = Chip8Batch::new(&SEEDS);
+    single.load_rom(&rom);
+    threaded.load_rom(&rom);
+    for frame in 0..FRAMES {
+        let keys: Vec<u16> = (0..SEEDS.len())
+            .map(|machine| keys_for(machine, frame))
+            .collect();
+        assert!(single.set_all_keys(&keys));
+        assert!(threaded.set_all_keys(&keys));
+        single.step();
+        threaded.step_threaded(3);
+    }
+    assert_eq!(single.display_rows(), threaded.display_rows());
+    assert!(single.frame_buffers() == threaded.frame_buffers());
+    assert_eq!(single.halted(), threaded.halted());
+    assert!(single.memory() == threaded.memory());
+    assert_eq!(single.registers(), threaded.registers());
+    assert_eq!(single.program_counters(), threaded.program_counters());
+}
+
+#[test]
+fn missing_machines_are_reported() {
+    let mut batch = Chip8Batch::new(&SEEDS);
+    batch.load_rom(&load("BRIX.ch8"));
+    let past = SEEDS.len();
+    assert!(!batch.reset(past, 1));
+    assert!(!batch.set_keys(past, 1));
+    assert!(!batch.set_all_keys(&[0; 2]));
+    assert_eq!(batch.get_seed(past), None);
+    assert_eq!(batch.get_keys(past), None);
+    assert_eq!(batch.is_beeping(past), None);
+    assert_eq!(batch.is_halted(past), None);
+    assert_eq!(batch.get_keys(0), Some(0));
+}
@@ -0,0 +1,238 @@
+//! The C API called the way a C host would, checked against the Rust API, and
+//! the checked-in header checked against what cbindgen generates from
+//! `src/capi.rs`.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{ffi::CStr, fs, os::raw::c_int, path::PathBuf, ptr};
+
+use chip8_emulator::{capi::*, Chip8};
+
+const SEED: u64 = 7;
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn load(name: &str) -> Vec<u8> {
+    let path = manifest_dir().join("../../test_roms").join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err))
+}
+
+fn screen(chip8: *const Chip8) -> Vec<u8> {
+    let mut pixels = vec![0; CHIP8_SCREEN_SIZE];
+    let status = unsafe { chip8_screen(chip8, pixels.as_mut_ptr(), pixels.len()) };
+    assert_eq!(status, Chip8Status::Ok);
+    pixels
+}
+
+fn save(chip8: *mut Chip8) -> Vec<u8> {
+    let mut size = 0;
+    let status = unsafe { chip8_save_state(chip8, ptr::null_mut(), 0, &mut size) };
+    assert_eq!(status, Chip8Status::BufferTooSmall);
+    let mut state = vec![0; size];
+    let status = unsafe { chip8_save_state(chip8, state.as_mut_ptr(), state.len(), &mut size) };
+    assert_eq!(status, Chip8Status::Ok);
+    assert_eq!(size, state.len());
+    state
+}
+
+#[test]
+fn header_is_up_to_date() {
+    let dir = manifest_dir();
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/capi.rs"))
+        .generate()
+        .expect("failed to generate the header")
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    let path = dir.join("include/chip8.h");
+    if std::env::var_os("CHIP8_UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).unwrap();
+    }
+    let header = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        header == generated,
+        "{} is out of date; run `CHIP8_UPDATE_HEADER=1 cargo test --test capi`",
+        path.display()
+    );
+}
+
+#[test]
+fn machine_matches_the_rust_api() {
+    let rom = load("BRIX.ch8");
+    let mut expected = Chip8::with_seed(SEED);
+    expected.set_auto_detect(true);
+    expected.load_rom(&rom);
+
+    let chip8 = chip8_new(SEED);
+    assert!(!chip8.is_null());
+    unsafe {
+        assert_eq!(
+            chip8_load_rom(chip8, rom.as_ptr(), rom.len()),
+            Chip8Status::Ok
+        );
+        for frame in 0..300 {
+            // Hold the paddle keys in turn
+            let key = if frame / 50 % 2 == 0 { 0x4 } else { 0x6 };
+            assert_eq!(chip8_set_key(chip8, key, true), Chip8Status::Ok);
+            expected.set_key(key);
+            assert_eq!(chip8_run_frame(chip8), Chip8Status::Ok);
+            expected.run_frame();
+            assert_eq!(chip8_set_key(chip8, key, false), Chip8Status::Ok);
+            expected.unset_key(key);
+        }
+    }
+    assert_eq!(screen(chip8), expected.frame_buffer());
+    unsafe { chip8_free(chip8) };
+}
+
+#[test]
+fn known_roms_get_their_settings() {
+    // The ROM database runs BLINKY at 15 instructions a frame
+    let rom = load("BLINKY.ch8");
+    assert_ne!(Chip8::new().get_ticks_per_frame(), 15);
+    let chip8 = chip8_new(SEED);
+    unsafe {
+        assert_eq!(
+            chip8_load_rom(chip8, rom.as_ptr(), rom.len()),
+            Chip8Status::Ok
+        );
+        let machine = &*chip8;
+        assert_eq!(machine.get_ticks_per_frame(), 15);
+        assert_eq!(machine.get_rom_info().unwrap().title(), "Blinky");
+        assert_eq!(machine.get_platform_guess().unwrap().confidence, 1.0);
+        chip8_free(chip8);
+    }
+}
+
+#[test]
+fn save_state_round_trips() {
+    let rom = load("BRIX.ch8");
+    let chip8 = chip8_new(SEED);
+    unsafe {
+        chip8_load_rom(chip8, rom.as_ptr(), rom.len());
+        for _ in 0..60 {
+            chip8_run_frame(chip8);
+        }
+        let state = save(chip8);
+        for _ in 0..120 {
+            chip8_run_frame(chip8);
+        }
+        let later = screen(chip8);
+
+        assert_eq!(
+            chip8_load_state(chip8, state.as_ptr(), state.len()),
+            Chip8Status::Ok
+        );
+        for _ in 0..120 {
+            chip8_run_frame(chip8);
+        }
+        assert_eq!(screen(chip8), later);
+        chip8_free(chip8);
+    }
+}
+
+#[test]
+fn errors_are_status_codes() {
+    let chip8 = chip8_new(SEED);
+    let mut pixels = [0; CHIP8_SCREEN_SIZE];
+    let mut size = 0;
+    unsafe {
+        assert_eq!(chip8_run_frame(ptr::null_mut()), Chip8Status::NullPointer);
+        assert_eq!(
+            chip8_load_rom(chip8, ptr::null(), 2),
+            Chip8Status::NullPointer
+        );
+        assert_eq!(
+            chip8_save_state(chip8, ptr::null_mut(), 0, ptr::null_mut()),
+            Chip8Status::NullPointer
+        );
+        assert_eq!(
+            chip8_set_key(chip8, CHIP8_KEY_COUNT, true),
+            Chip8Status::InvalidArgument
+        );
+        let huge = vec![0; 4096];
+        assert_eq!(
+            chip8_load_rom(chip8, huge.as_ptr(), huge.len()),
+            Chip8Status::InvalidArgument
+        );
+        assert_eq!(
+            chip8_screen(chip8, pixels.as_mut_ptr(), pixels.len() - 1),
+            Chip8Status::BufferTooSmall
+        );
+        let mut state = [0; 4];
+        assert_eq!(
+            chip8_save_state(chip8, state.as_mut_ptr(), state.len(), &mut size),
+            Chip8Status::BufferTooSmall
+        );
+        assert!(size > state.len());
+        assert_eq!(
+            chip8_load_state(chip8, state.as_ptr(), state.len()),
+            Chip8Status::InvalidState
+        );
+        // Freeing null does nothing
+        chip8_free(ptr::null_mut());
+        chip8_free(chip8);
+    }
+}
+
+#[test]
+fn faults_halt_the_machine() {
+    // 0xFFFF is not an instruction
+    let rom = [0xFF, 0xFF];
+    let chip8 = chip8_new(SEED);
+    unsafe {
+        assert_eq!(
+            chip8_load_rom(chip8, rom.as_ptr(), rom.len()),
+            Chip8Status::Ok
+        );
+        assert_eq!(chip8_run_frame(chip8), Chip8Status::Halted);
+        assert_eq!(chip8_run_frame(chip8), Chip8Status::Halted);
+        assert_eq!(chip8_reset(chip8), Chip8Status::Ok);
+
+        // 6000 F029 D005 1206: draw the 0 glyph at the top left and spin
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+        assert_eq!(
+            chip8_load_rom(chip8, rom.as_ptr(), rom.len()),
+            Chip8Status::Ok
+        );
+        assert_eq!(chip8_run_frame(chip8), Chip8Status::Ok);
+        let mut screen = [0; CHIP8_SCREEN_SIZE];
+        assert_eq!(
+            chip8_screen(chip8, screen.as_mut_ptr(), screen.len()),
+            Chip8Status::Ok
+        );
+        assert_eq!(screen[..5], [1, 1, 1, 1, 0]);
+        chip8_free(chip8);
+    }
+}
+
+#[test]
+fn every_status_has_a_message() {
+    for status in [
+        Chip8Status::Ok,
+        Chip8Status::NullPointer,
+        Chip8Status::InvalidArgument,
+        Chip8Status::BufferTooSmall,
+        Chip8Status::InvalidState,
+        Chip8Status::Halted,
+        Chip8Status::Panic,
+    ] {
+        let message = unsafe { CStr::from_ptr(chip8_status_message(status as c_int)) };
+        assert!(!message.to_bytes().is_empty(), "{:?}", status);
+        assert_ne!(message.to_bytes(), b"unknown status", "{:?}", status);
+    }
+}
+
+#[test]
+fn unknown_statuses_have_a_message() {
+    for status in [-1, 7, c_int::MAX] {
+        let message = unsafe { CStr::from_ptr(chip8_status_message(status)) };
+        assert_eq!(message.to_bytes(), b"unknown status");
+    }
+}
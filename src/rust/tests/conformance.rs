@@ -0,0 +1,96 @@
+//! Runs the bundled conformance ROMs under every quirks profile.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{
+    conformance::{self, Outcome, SuiteStatus, PENDING_SUITES, SUITES},
+    QUIRK_PROFILES,
+};
+
+fn test_roms() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_roms")
+}
+
+#[test]
+fn bundled_suites_pass_under_every_profile() {
+    let reports = conformance::run_all(|name| fs::read(test_roms().join(name)).ok());
+
+    let mut ran = 0;
+    for report in &reports {
+        if let SuiteStatus::Ran(results) = &report.status {
+            ran += 1;
+            let failed: Vec<_> = results
+                .iter()
+                .filter(|result| result.outcome != Outcome::Pass)
+                .collect();
+            assert!(
+                failed.is_empty(),
+                "{} under {}: {:?}",
+                report.suite,
+                report.profile,
+                failed
+            );
+        }
+    }
+    assert!(ran > 0, "no conformance suite was found in test_roms/");
+}
+
+#[test]
+fn every_suite_is_bundled_and_checked() {
+    let reports = conformance::run_all(|name| fs::read(test_roms().join(name)).ok());
+    for report in &reports {
+        assert!(
+            report.passed(),
+            "{} under {}: {:?}",
+            report.suite,
+            report.profile,
+            report.status
+        );
+    }
+    assert_eq!(reports.len(), SUITES.len() * QUIRK_PROFILES.len());
+}
+
+// Bundling one of these ROMs means mapping its result screen too
+#[test]
+fn pending_suites_are_not_bundled() {
+    for (suite, rom) in PENDING_SUITES {
+        assert!(
+            !test_roms().join(rom).exists(),
+            "{} is bundled; map its results and move it into SUITES",
+            suite
+        );
+        assert!(SUITES.iter().all(|checked| checked.rom != *rom));
+    }
+}
+
+#[test]
+fn unexpected_pictures_are_unreadable() {
+    let ibm = SUITES
+        .iter()
+        .find(|suite| suite.name == "ibm-logo")
+        .unwrap();
+    let frame = vec![0u8; 64 * 32];
+    assert_eq!(ibm.read_results(&frame)[0].outcome, Outcome::Unreadable);
+}
+
+#[test]
+fn failed_sub_tests_are_read_back() {
+    let corax = SUITES.iter().find(|suite| suite.name == "corax").unwrap();
+    let mut frame = vec![0u8; 64 * 32];
+
+    // Draw the NO glyph into the first result cell
+    let cell = &corax.cells[0];
+    for (row, pattern) in corax.fail.as_ref().unwrap().rows.iter().enumerate() {
+        for (col, pixel) in pattern.bytes().enumerate() {
+            frame[(cell.y + row) * 64 + cell.x + col] = (pixel == b'#') as u8;
+        }
+    }
+
+    let results = corax.read_results(&frame);
+    assert_eq!(results[0].outcome, Outcome::Fail);
+    assert!(results[1..]
+        .iter()
+        .all(|result| result.outcome == Outcome::Unreadable));
+}
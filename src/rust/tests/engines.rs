@@ -0,0 +1,107 @@
+//! Differential test of the execution engines: every ROM in `test_roms/` must
+//! put the machine in the same state after every frame whether it runs on the
+//! interpreter, the cached-decode engine or the block JIT.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{Chip8, ExecutionEngine};
+
+const SEED: u64 = 0x5EED;
+const FRAMES: u32 = 300;
+
+// `vip` keeps the display and the stack in memory, so draws and calls write
+// over addresses the engines may have cached
+fn machine(rom: &[u8], engine: ExecutionEngine, vip: bool) -> Chip8 {
+    let mut chip8 = Chip8::with_seed(SEED);
+    chip8.set_execution_engine(engine);
+    chip8.set_vip_layout(vip);
+    chip8.set_stack_in_memory(vip);
+    chip8.load_rom(rom);
+    chip8
+}
+
+// Holds each key for a while in turn so input paths run too
+fn run_frame(chip8: &mut Chip8, frame: u32) {
+    let key = (frame / 20 % 16) as u8;
+    if frame.is_multiple_of(20) {
+        chip8.set_key(key);
+    } else if frame % 20 == 10 {
+        chip8.unset_key(key);
+    }
+    chip8.run_frame();
+}
+
+fn assert_same_state(actual: &Chip8, expected: &Chip8, context: &str) {
+    assert_eq!(actual.registers(), expected.registers(), "{}", context);
+    assert_eq!(
+        actual.index_register(),
+        expected.index_register(),
+        "{}",
+        context
+    );
+    assert_eq!(
+        actual.program_counter(),
+        expected.program_counter(),
+        "{}",
+        context
+    );
+    assert_eq!(actual.delay_timer(), expected.delay_timer(), "{}", context);
+    assert_eq!(actual.sound_timer(), expected.sound_timer(), "{}", context);
+    // The stack pointer is the stack's length
+    assert_eq!(actual.stack(), expected.stack(), "{}", context);
+    assert_eq!(
+        actual.display_rows(),
+        expected.display_rows(),
+        "{}",
+        context
+    );
+    assert_eq!(actual.error(), expected.error(), "{}", context);
+    assert_eq!(actual.get_keys(), expected.get_keys(), "{}", context);
+    assert_eq!(actual.is_beeping(), expected.is_beeping(), "{}", context);
+    // Memory holds everything the ROM stored, including BCD digits and
+    // saved registers
+    assert!(
+        actual.memory() == expected.memory(),
+        "memory differs for {}",
+        context
+    );
+}
+
+fn assert_matches_interpreter(engine: ExecutionEngine) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_roms");
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .expect("test_roms directory is missing")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".ch8"))
+        .collect();
+    names.sort();
+    assert!(!names.is_empty());
+
+    for (name, vip) in names.iter().flat_map(|name| [(name, false), (name, true)]) {
+        let rom = fs::read(dir.join(name)).unwrap();
+        let mut expected = machine(&rom, ExecutionEngine::Interpreter, vip);
+        let mut actual = machine(&rom, engine, vip);
+        for frame in 0..FRAMES {
+            run_frame(&mut expected, frame);
+            run_frame(&mut actual, frame);
+            let context = format!(
+                "{} on {:?}, VIP layout {}, frame {}",
+                name, engine, vip, frame
+            );
+            assert_same_state(&actual, &expected, &context);
+        }
+    }
+}
+
+#[test]
+fn cached_decode_matches_interpreter() {
+    assert_matches_interpreter(ExecutionEngine::CachedDecode);
+}
+
+#[test]
+fn block_jit_matches_interpreter() {
+    assert_matches_interpreter(ExecutionEngine::BlockJit);
+}
@@ -0,0 +1,193 @@
+//! The reinforcement learning environment on the games it has rewards for:
+//! action spaces come from the keys each ROM tests, rewards follow the score
+//! and episodes end with the game.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{
+    env::{Env, InvalidAction, Observation, RewardError, RewardTable},
+    Chip8,
+};
+
+const MAX_STEPS: u32 = 20_000;
+
+fn load(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test_roms")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err))
+}
+
+// Plays random actions from a fixed xorshift sequence until the episode ends
+fn play(env: &mut Env, seed: u64) -> (Vec<Observation>, f32) {
+    let mut observations = vec![env.reset(seed)];
+    let mut total = 0.0;
+    let mut state = seed | 1;
+    for _ in 0..MAX_STEPS {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let (observation, reward, done) = env.step(state as usize % env.action_count()).unwrap();
+        observations.push(observation);
+        total += reward;
+        if done {
+            return (observations, total);
+        }
+    }
+    panic!("episode did not end within {} steps", MAX_STEPS);
+}
+
+#[test]
+fn actions_are_the_keys_each_game_tests() {
+    let expected: &[(&str, &[u8])] = &[
+        ("PONG.ch8", &[0x1, 0x4, 0xC, 0xD]),
+        ("BRIX.ch8", &[0x4, 0x6]),
+        ("INVADERS.ch8", &[0x4, 0x5, 0x6]),
+    ];
+    for (name, keys) in expected {
+        let env = Env::new(&load(name));
+        let mut actions = vec![None];
+        actions.extend(keys.iter().copied().map(Some));
+        assert_eq!(env.action_keys(), &actions[..], "{}", name);
+        assert!(env.rewards().is_some(), "{}", name);
+    }
+}
+
+#[test]
+fn pong_ends_when_a_player_reaches_nine() {
+    let mut env = Env::new(&load("PONG.ch8"));
+    let (_, total) = play(&mut env, 1);
+    // Points for the left paddle minus points for the right one
+    let memory = env.chip8().memory();
+    assert_eq!(total, memory[0x2F3] as f32 - memory[0x2F4] as f32);
+    assert!(memory[0x2F3] == 9 || memory[0x2F4] == 9);
+}
+
+#[test]
+fn brix_reward_is_the_score_and_ends_with_the_last_ball() {
+    let mut env = Env::new(&load("BRIX.ch8"));
+    let (_, total) = play(&mut env, 2);
+    let memory = env.chip8().memory();
+    let score = memory[0x314] as u32 * 100 + memory[0x315] as u32 * 10 + memory[0x316] as u32;
+    assert!(total > 0.0);
+    assert_eq!(total, score as f32);
+    assert!(env.chip8().error().is_none());
+}
+
+#[test]
+fn invaders_skips_the_title_and_rewards_hits() {
+    let rom = load("INVADERS.ch8");
+    let mut env = Env::new(&rom);
+    let (observations, total) = play(&mut env, 3);
+    assert!(total > 0.0);
+
+    // The title screen stays up until key 5 is pressed
+    let mut title = Chip8::new();
+    title.load_rom(&rom);
+    for _ in 0..40 {
+        title.run_frame();
+    }
+    assert_ne!(observations[0].screen, title.frame_buffer());
+}
+
+#[test]
+fn reset_replays_an_episode() {
+    let mut env = Env::new(&load("BRIX.ch8"));
+    env.set_frame_skip(2);
+    let first = play(&mut env, 4);
+    let second = play(&mut env, 4);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn every_env_for_a_rom_starts_the_same() {
+    // C0FF A300 F055 1206: store a random byte at 0x300 during the start
+    // frames, then spin
+    let rom = [0xC0, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+    let sha1 = sha1_smol::Sha1::from(rom).digest().to_string();
+    let json = format!(
+        r#"[{{"sha1": "{}", "title": "Random", "score": [{{"memory": "0x300", "weight": 1}}],
+            "start": {{"key": 0, "frames": 1}}}}]"#,
+        sha1
+    );
+    let table = RewardTable::parse(&json).unwrap();
+    let mut first = Env::with_rewards(&rom, &table);
+    let mut second = Env::with_rewards(&rom, &table);
+    for seed in 0..4 {
+        assert_eq!(first.reset(seed), second.reset(seed));
+        assert!(first.chip8().save_state() == second.chip8().save_state());
+    }
+}
+
+#[test]
+fn roms_without_rewards_end_at_the_step_limit() {
+    let mut env = Env::new(&load("TANK.ch8"));
+    assert!(env.rewards().is_none());
+    env.set_max_steps(Some(50));
+    let (observations, total) = play(&mut env, 5);
+    assert_eq!(observations.len(), 51);
+    assert_eq!(total, 0.0);
+}
+
+#[test]
+fn out_of_range_actions_are_refused() {
+    let mut env = Env::new(&load("PONG.ch8"));
+    env.reset(6);
+    let before = env.chip8().save_state();
+    let count = env.action_count();
+    assert_eq!(
+        env.step(count),
+        Err(InvalidAction {
+            action: count,
+            count
+        })
+    );
+    // Nothing ran
+    assert_eq!(env.chip8().save_state(), before);
+}
+
+#[test]
+fn reward_tables_can_come_from_elsewhere() {
+    let rom = load("TANK.ch8");
+    let sha1 = sha1_smol::Sha1::from(&rom).digest().to_string();
+    let json = format!(
+        r#"[{{"sha1": "{}", "title": "Tank", "score": [{{"register": "V0", "weight": 1}}],
+            "done_when": [{{"memory": "0x300", "equals": 1}}]}}]"#,
+        sha1
+    );
+    let path = std::env::temp_dir().join(format!("chip8-rewards-{}.json", std::process::id()));
+    fs::write(&path, &json).unwrap();
+    let table = RewardTable::load(&path);
+    fs::remove_file(&path).unwrap();
+    let table = table.unwrap();
+
+    let env = Env::with_rewards(&rom, &table);
+    let rewards = env.rewards().unwrap();
+    assert_eq!(rewards.title, "Tank");
+    assert_eq!(rewards.score.len(), 1);
+    assert_eq!(rewards.done_when.len(), 1);
+    // The built-in table knows nothing of it
+    assert!(Env::new(&rom).rewards().is_none());
+    assert!(Env::with_rewards(&load("PONG.ch8"), &table)
+        .rewards()
+        .is_none());
+}
+
+#[test]
+fn bad_reward_tables_are_reported() {
+    assert!(matches!(
+        RewardTable::parse("not json"),
+        Err(RewardError::Json(_))
+    ));
+    assert!(matches!(
+        RewardTable::parse(r#"[{"sha1": "00", "title": "X", "score": []}]"#),
+        Err(RewardError::Invalid(_))
+    ));
+    assert!(matches!(
+        RewardTable::load("/nonexistent/rewards.json"),
+        Err(RewardError::Io(_))
+    ));
+    assert!(RewardTable::parse("[]").unwrap().entries().is_empty());
+}
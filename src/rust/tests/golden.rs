@@ -0,0 +1,199 @@
+//! Golden-frame regression tests over the ROMs in `test_roms/`.
+//!
+//! Each ROM runs for a fixed number of frames with a fixed seed and a scripted
+//! key sequence. The final frame buffer is hashed and compared against the
+//! checked-in hash in `tests/golden/hashes.txt`; an ASCII rendering of the
+//! expected screen lives next to it for review.
+//!
+//! Run with `BLESS=1 cargo test --test golden` to regenerate the golden files
+//! after an intentional behaviour change.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use chip8_emulator::Chip8;
+
+const SEED: u64 = 0xC8C8_C8C8;
+const FRAMES: u32 = 600;
+const WIDTH: usize = 64;
+
+struct InputEvent {
+    frame: u32,
+    key: u8,
+    pressed: bool,
+}
+
+const fn press(frame: u32, key: u8) -> InputEvent {
+    InputEvent {
+        frame,
+        key,
+        pressed: true,
+    }
+}
+
+const fn release(frame: u32, key: u8) -> InputEvent {
+    InputEvent {
+        frame,
+        key,
+        pressed: false,
+    }
+}
+
+// Taps the keys most ROMs use to start and move so that menus are dismissed
+// and some gameplay is exercised. Must stay sorted by frame.
+const SCRIPT: &[InputEvent] = &[
+    press(60, 0x5),
+    release(66, 0x5),
+    press(120, 0x4),
+    release(150, 0x4),
+    press(180, 0x6),
+    release(210, 0x6),
+    press(240, 0x1),
+    release(270, 0x1),
+    press(300, 0xC),
+    release(330, 0xC),
+    press(360, 0x7),
+    release(390, 0x7),
+    press(420, 0x8),
+    release(426, 0x8),
+    press(480, 0x9),
+    release(510, 0x9),
+];
+
+fn repo_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn run_rom(rom: &[u8]) -> Vec<u8> {
+    let mut chip8 = Chip8::with_seed(SEED);
+    chip8.load_rom(rom);
+
+    let mut events = SCRIPT.iter().peekable();
+    for frame in 0..FRAMES {
+        while let Some(event) = events.next_if(|event| event.frame == frame) {
+            if event.pressed {
+                chip8.set_key(event.key);
+            } else {
+                chip8.unset_key(event.key);
+            }
+        }
+        chip8.run_frame();
+    }
+    chip8.frame_buffer().to_vec()
+}
+
+// FNV-1a, stable across platforms and Rust versions
+fn hash_frame(pixels: &[u8]) -> u64 {
+    pixels.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &pixel| {
+        (hash ^ pixel as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn render_ascii(pixels: &[u8]) -> String {
+    pixels
+        .chunks(WIDTH)
+        .map(|row| {
+            let mut line: String = row
+                .iter()
+                .map(|&pixel| if pixel != 0 { '#' } else { '.' })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+fn rom_names() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(repo_root().join("test_roms"))
+        .expect("test_roms directory is missing")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".ch8"))
+        .collect();
+    names.sort();
+    names
+}
+
+fn read_hashes() -> BTreeMap<String, u64> {
+    let contents = fs::read_to_string(golden_dir().join("hashes.txt")).unwrap_or_default();
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (name, hash) = line
+                .split_once(' ')
+                .unwrap_or_else(|| panic!("malformed golden line: {}", line));
+            let hash = u64::from_str_radix(hash.trim(), 16)
+                .unwrap_or_else(|_| panic!("malformed golden hash: {}", line));
+            (name.to_string(), hash)
+        })
+        .collect()
+}
+
+fn write_hashes(hashes: &BTreeMap<String, u64>) {
+    let mut contents = String::from("# ROM name and FNV-1a hash of the final frame buffer\n");
+    for (name, hash) in hashes {
+        contents.push_str(&format!("{} {:016x}\n", name, hash));
+    }
+    fs::write(golden_dir().join("hashes.txt"), contents).unwrap();
+}
+
+#[test]
+fn golden_frames_match() {
+    let bless = env::var_os("BLESS").is_some();
+    let expected = read_hashes();
+    let mut actual = BTreeMap::new();
+    let mut failures = Vec::new();
+
+    for name in rom_names() {
+        let rom = fs::read(repo_root().join("test_roms").join(&name)).unwrap();
+        let pixels = run_rom(&rom);
+        let hash = hash_frame(&pixels);
+        let image_path = golden_dir().join(format!("{}.txt", name));
+
+        if bless {
+            fs::write(&image_path, render_ascii(&pixels)).unwrap();
+        } else {
+            match expected.get(&name) {
+                Some(&golden) if golden == hash => {}
+                Some(&golden) => failures.push(format!(
+                    "{}: expected {:016x}, got {:016x}\nexpected:\n{}\nactual:\n{}",
+                    name,
+                    golden,
+                    hash,
+                    fs::read_to_string(&image_path).unwrap_or_default(),
+                    render_ascii(&pixels)
+                )),
+                None => failures.push(format!("{}: no golden hash recorded", name)),
+            }
+        }
+        actual.insert(name, hash);
+    }
+
+    if bless {
+        write_hashes(&actual);
+        return;
+    }
+
+    for name in expected.keys().filter(|name| !actual.contains_key(*name)) {
+        failures.push(format!("{}: golden hash recorded but ROM is missing", name));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} golden frame mismatch(es); rerun with BLESS=1 if the change is intended\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn runs_are_deterministic() {
+    let rom = fs::read(repo_root().join("test_roms/BRIX.ch8")).unwrap();
+    assert_eq!(run_rom(&rom), run_rom(&rom));
+}
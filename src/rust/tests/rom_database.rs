@@ -0,0 +1,52 @@
+//! Checks the compiled-in ROM database against the bundled ROMs.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{fs, path::PathBuf};
+
+use chip8_emulator::{lookup_rom, Chip8};
+
+fn test_roms() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_roms")
+}
+
+#[test]
+fn every_bundled_rom_is_listed() {
+    let mut missing = Vec::new();
+    for entry in fs::read_dir(test_roms()).unwrap() {
+        let path = entry.unwrap().path();
+        let rom = fs::read(&path).unwrap();
+        if lookup_rom(&rom).is_none() {
+            missing.push(path.file_name().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    assert!(missing.is_empty(), "not in roms.json: {:?}", missing);
+}
+
+#[test]
+fn load_rom_applies_the_listed_profile() {
+    let rom = fs::read(test_roms().join("BLINKY.ch8")).unwrap();
+    let info = lookup_rom(&rom).unwrap();
+    assert_eq!(info.title(), "Blinky");
+    assert_eq!(info.key_for("arrowup"), Some(3));
+
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&rom);
+    assert_eq!(
+        chip8.get_rom_info().map(|info| info.title()),
+        Some("Blinky".to_string())
+    );
+    assert_eq!(chip8.get_ticks_per_frame(), 7);
+
+    chip8.reset();
+    chip8.set_auto_detect(true);
+    chip8.load_rom(&rom);
+    assert_eq!(chip8.get_ticks_per_frame(), info.ticks_per_frame());
+    assert_eq!(chip8.get_quirks(), info.quirks());
+    assert_eq!(chip8.get_platform_guess().unwrap().confidence, 1.0);
+}
+
+#[test]
+fn unknown_roms_are_not_found() {
+    assert!(lookup_rom(&[0x12, 0x00]).is_none());
+}
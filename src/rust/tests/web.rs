@@ -5,9 +5,45 @@
 extern crate wasm_bindgen_test;
 use wasm_bindgen_test::*;
 
+use chip8_emulator::{Chip8, ExecutionEngine};
+
 wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+fn run(rom: &[u8], engine: ExecutionEngine) -> Chip8 {
+    let mut chip8 = Chip8::with_seed(1);
+    chip8.set_execution_engine(engine);
+    chip8.load_rom(rom);
+    for _ in 0..120 {
+        chip8.run_frame();
+    }
+    chip8
+}
+
+// The block JIT compiles register arithmetic to WebAssembly here, which must
+// leave the same state as the interpreter
+#[wasm_bindgen_test]
+fn block_jit_matches_interpreter() {
+    // 200: LD V0, 0x7F; 202: ADD V1, 0x33; 204: ADD V1, V0; 206: SUB V2, V1
+    // 208: SUBN V3, V1; 20A: LD V4, V2; 20C: LD F, V3; 20E: ADD I, V4
+    // 210: JP 0x202
+    let arithmetic = [
+        0x60, 0x7F, 0x71, 0x33, 0x81, 0x04, 0x82, 0x15, 0x83, 0x17, 0x84, 0x20, 0xF3, 0x29, 0xF4,
+        0x1E, 0x12, 0x02,
+    ];
+    for rom in [
+        &arithmetic[..],
+        include_bytes!("../../../test_roms/BRIX.ch8"),
+    ] {
+        let expected = run(rom, ExecutionEngine::Interpreter);
+        let actual = run(rom, ExecutionEngine::BlockJit);
+        assert_eq!(actual.registers(), expected.registers());
+        assert_eq!(actual.index_register(), expected.index_register());
+        assert_eq!(actual.program_counter(), expected.program_counter());
+        assert_eq!(actual.frame_buffer(), expected.frame_buffer());
+    }
+}
@@ -1,8 +1,9 @@
-import { Chip8 } from '../../../pkg';
+import { BuiltinPalette, Chip8, FlickerFilter, Recorder, Upscaler } from '../../../pkg';
 import * as wasm from '../../../pkg/chip8_emulator_bg.wasm';
 import '../style/main.css';
 
-// Export update_canvas function for WASM to call
+// Export update_canvas function for WASM to call; the core calls it at most
+// once per frame, when the screen changed
 (window as any).update_canvas = function () {
   updateDisplay();
 };
@@ -17,21 +18,29 @@ const HEIGHT: number = 32;
 const SCALE = 15;
 
 const chip8 = Chip8.new();
+// Pick quirks for each ROM from its code instead of a fixed profile
+chip8.set_auto_detect(true);
 const canvas: HTMLCanvasElement = createCanvas();
 const ctx: CanvasRenderingContext2D = canvas.getContext('2d')!;
-var pixels: Uint8Array;
 const bufferCanvas: HTMLCanvasElement = document.createElement('canvas');
 const bufferCtx: CanvasRenderingContext2D = bufferCanvas.getContext('2d')!;;
 const imageData: ImageData = bufferCtx.createImageData(WIDTH, HEIGHT);;
 var lastTime = 0;
 const timerCycleTime = 1000 / 60; // 60Hz timers
+// Longest stretch of time handed to the core at once, so a backgrounded tab
+// does not fast-forward the game when it comes back
+const MAX_FRAME_TIME_MS = 100;
 var lastTimerUpdate = 0;
 var animationFrameId: number | null = null;
 var audioContext: AudioContext | null = null;
-
-// Initialize pixels array once
-const screenDataPtr = chip8.get_screen();
-pixels = new Uint8Array(wasm.memory.buffer, screenDataPtr, WIDTH * HEIGHT);
+var recorder: Recorder | null = null;
+const RECORD_SCALE = 4;
+// Upscaler run by the core before the canvas stretches its output to fit;
+// null shows the plain screen
+var upscaler: Upscaler | null = null;
+const UPSCALE_FACTOR = 3;
+const upscaledCanvas: HTMLCanvasElement = document.createElement('canvas');
+const upscaledCtx: CanvasRenderingContext2D = upscaledCanvas.getContext('2d')!;
 
 function createCanvas(): HTMLCanvasElement {
   const canvas = document.createElement('canvas');
@@ -47,20 +56,35 @@ bufferCanvas.height = HEIGHT;
 
 
 
-function updateDisplay() {
-  // Update ImageData directly from pixel buffer
-  for (let i = 0; i < pixels.length; i++) {
-    const pixelIndex = i * 4;
-    const color = pixels[i] ? 255 : 0; // 255 = white, 0 = black
+// Copies only the pixels the core reports as changed, unless `redrawAll` is
+// set because something outside the screen, like the palette, changed
+function updateDisplay(redrawAll = false) {
+  const dirty = chip8.take_dirty_region();
+  let x = 0, y = 0, width = WIDTH, height = HEIGHT;
+  if (dirty) {
+    if (!redrawAll) {
+      ({ x, y, width, height } = dirty);
+    }
+    dirty.free();
+  } else if (!redrawAll) {
+    return;
+  }
+
+  if (upscaler !== null) {
+    drawUpscaled();
+    return;
+  }
 
-    imageData.data[pixelIndex] = color;     // Red
-    imageData.data[pixelIndex + 1] = color; // Green  
-    imageData.data[pixelIndex + 2] = color; // Blue
-    imageData.data[pixelIndex + 3] = 255;   // Alpha (always opaque)
+  // The core renders the screen in its palette; the view is made on every
+  // call because growing wasm memory detaches old ones
+  const rgba = new Uint8ClampedArray(wasm.memory.buffer, chip8.get_rgba_screen(), WIDTH * HEIGHT * 4);
+  for (let row = y; row < y + height; row++) {
+    const start = (row * WIDTH + x) * 4;
+    imageData.data.set(rgba.subarray(start, start + width * 4), start);
   }
 
-  // Put image data on the small buffer canvas
-  bufferCtx.putImageData(imageData, 0, 0);
+  // Put the changed part of the image data on the small buffer canvas
+  bufferCtx.putImageData(imageData, 0, 0, x, y, width, height);
 
   // Clear main canvas and scale up the buffer canvas
   ctx.clearRect(0, 0, canvas.width, canvas.height);
@@ -72,18 +96,46 @@ function updateDisplay() {
   );
 }
 
+// Upscalers look at neighbouring pixels, so the whole screen is redrawn
+function drawUpscaled() {
+  const width = chip8.get_upscaled_width();
+  const height = chip8.get_upscaled_height();
+  // Rendering may grow wasm memory, so take the pointer before the buffer
+  const ptr = chip8.get_upscaled_screen();
+  const rgba = new Uint8ClampedArray(wasm.memory.buffer, ptr, width * height * 4);
+  upscaledCanvas.width = width;
+  upscaledCanvas.height = height;
+  upscaledCtx.putImageData(new ImageData(rgba.slice(), width, height), 0, 0);
+
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.imageSmoothingEnabled = false;
+  ctx.drawImage(
+    upscaledCanvas,
+    0, 0, width, height,
+    0, 0, canvas.width, canvas.height
+  );
+}
+
 function mainLoop(currentTime: number) {
   const deltaTime = currentTime - lastTime;
   lastTime = currentTime;
 
-  // CPU runs at browser refresh rate (usually 60Hz)
-  for (let i = 0; i < 7; i++) {
-    chip8.tick();
+  // The core owns timing: it runs however many instructions and 60Hz timer
+  // ticks fit in the time since the last animation frame
+  chip8.run_for(Math.round(Math.min(deltaTime, MAX_FRAME_TIME_MS) * 1000));
+
+  const error = chip8.get_error();
+  if (error) {
+    console.error(`Emulator halted: ${error}`);
+    stopMainLoop();
+    return;
+  }
+
+  if (recorder) {
+    recorder.capture(chip8);
   }
 
-  // Timer updates (60Hz) - only decrement timers at 60Hz even if running faster
   if (currentTime - lastTimerUpdate >= timerCycleTime) {
-    // Timer decrements happen in chip8.tick()
     lastTimerUpdate = currentTime;
   }
   animationFrameId = requestAnimationFrame(mainLoop);
@@ -110,13 +162,39 @@ function restartEmulatorWithRom(romData: Uint8Array) {
 
   chip8.load_rom(romData);
 
-  // Clear the screen and update display
-  updateDisplay();
+  applyRomInfo();
+
+  const guess = chip8.get_platform_guess();
+  if (guess) {
+    console.log(`Detected platform ${guess.platform} (confidence ${guess.confidence.toFixed(2)})`);
+  }
+
+  // Clear the screen and redraw it in the ROM's colors
+  updateDisplay(true);
 
   // Start the main loop now that ROM is loaded
   startMainLoop();
 }
 
+// Uses the ROM database entry, if any, for extra key bindings. The core
+// applies its colors itself.
+function applyRomInfo() {
+  const info = chip8.get_rom_info();
+  if (!info) {
+    chip8.set_palette(BuiltinPalette.Monochrome);
+    return;
+  }
+
+  console.log(`Loaded ${info.title()} by ${info.author()}`);
+  for (const key of info.bound_keys()) {
+    const chip8Key = info.key_for(key);
+    if (chip8Key !== undefined && !globalKeyMap.hasOwnProperty(key)) {
+      globalKeyMap[key] = chip8Key;
+    }
+  }
+  info.free();
+}
+
 function setupRomButtons() {
   const romInput = document.getElementById('rom-file') as HTMLInputElement;
   const testRomSelect = document.getElementById('test-rom-select') as HTMLSelectElement;
@@ -191,6 +269,87 @@ function setupResetButton() {
   });
 }
 
+function setupRecordButton() {
+  const recordBtn = document.getElementById('record-btn') as HTMLButtonElement;
+  if (!recordBtn) {
+    console.error('Record button not found');
+    return;
+  }
+  const label = recordBtn.querySelector('.rom-text') as HTMLElement;
+
+  recordBtn.addEventListener('click', () => {
+    if (!recorder) {
+      recorder = Recorder.new();
+      label.textContent = 'Stop Recording';
+      return;
+    }
+
+    const gif = recorder.encode_gif(RECORD_SCALE);
+    console.log(`Recorded ${recorder.duration_frames()} frames (${recorder.frame_count()} unique)`);
+    recorder.free();
+    recorder = null;
+    label.textContent = 'Record Clip';
+
+    const url = URL.createObjectURL(new Blob([gif], { type: 'image/gif' }));
+    const link = document.createElement('a');
+    link.href = url;
+    link.download = 'chip8-clip.gif';
+    link.click();
+    URL.revokeObjectURL(url);
+  });
+}
+
+// Cycles through the core's anti-flicker filters
+function setupFlickerButton() {
+  const flickerBtn = document.getElementById('flicker-btn') as HTMLButtonElement;
+  if (!flickerBtn) {
+    console.error('Anti-flicker button not found');
+    return;
+  }
+  const label = flickerBtn.querySelector('.rom-text') as HTMLElement;
+  const modes: [FlickerFilter, string][] = [
+    [FlickerFilter.Off, 'Off'],
+    [FlickerFilter.Phosphor, 'Phosphor'],
+    [FlickerFilter.MergeLastTwo, 'Merge'],
+  ];
+  let current = 0;
+
+  flickerBtn.addEventListener('click', () => {
+    current = (current + 1) % modes.length;
+    const [mode, name] = modes[current];
+    chip8.set_flicker_filter(mode);
+    label.textContent = `Anti-Flicker: ${name}`;
+    updateDisplay(true);
+  });
+}
+
+function setupUpscaleButton() {
+  const upscaleBtn = document.getElementById('upscale-btn') as HTMLButtonElement;
+  if (!upscaleBtn) {
+    console.error('Upscale button not found');
+    return;
+  }
+  const label = upscaleBtn.querySelector('.rom-text') as HTMLElement;
+  const modes: [Upscaler | null, string][] = [
+    [null, 'Off'],
+    [Upscaler.Epx, 'EPX'],
+    [Upscaler.Hqx, 'HQx'],
+    [Upscaler.Crt, 'CRT'],
+  ];
+  let current = 0;
+
+  upscaleBtn.addEventListener('click', () => {
+    current = (current + 1) % modes.length;
+    const [mode, name] = modes[current];
+    upscaler = mode;
+    if (mode !== null) {
+      chip8.set_upscaler(mode, UPSCALE_FACTOR);
+    }
+    label.textContent = `Upscaler: ${name}`;
+    updateDisplay(true);
+  });
+}
+
 // Global key mapping
 let globalKeyMap: { [key: string]: number } = {
   '1': 0x1, '2': 0x2, '3': 0x3, '4': 0xC,
@@ -422,6 +581,9 @@ function main() {
   setupRomButtons();
   setupResetButton();
   setupRemapKeysButton();
+  setupRecordButton();
+  setupFlickerButton();
+  setupUpscaleButton();
 
   startMainLoop();
   // Don't start main loop until ROM is loaded